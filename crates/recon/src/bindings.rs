@@ -14,14 +14,22 @@ pub struct ReconState {
     pub wasi: wasmtime_wasi::WasiCtx,
     pub table: wasmtime_wasi::ResourceTable,
     pub bus: recon_bus::Bus,
+    pub principal: recon_bus::Principal,
+    pub replies: recon_bus::host::PendingReplies,
 }
 
 impl ReconState {
-    pub fn new(wasi: wasmtime_wasi::WasiCtx, bus: recon_bus::Bus) -> Self {
+    pub fn new(
+        wasi: wasmtime_wasi::WasiCtx,
+        bus: recon_bus::Bus,
+        principal: recon_bus::Principal,
+    ) -> Self {
         Self {
             wasi,
             table: wasmtime_wasi::ResourceTable::new(),
             bus,
+            principal,
+            replies: recon_bus::host::PendingReplies::new(),
         }
     }
 }
@@ -37,7 +45,11 @@ impl wasmtime_wasi::WasiView for ReconState {
 
 impl recon_bus::host::EventBusView for ReconState {
     fn event_bus(&mut self) -> recon_bus::host::EventBusCtx<'_> {
-        recon_bus::host::EventBusCtx { bus: &self.bus }
+        recon_bus::host::EventBusCtx {
+            bus: &self.bus,
+            principal: &self.principal,
+            replies: &self.replies,
+        }
     }
 }
 
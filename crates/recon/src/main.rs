@@ -2,6 +2,7 @@
 
 mod bindings;
 mod plugin_manager;
+mod system;
 pub(crate) mod utils;
 
 use iced::{
@@ -10,7 +11,11 @@ use iced::{
     window,
 };
 use plugin_manager::ReconPluginManager;
-use recon_bus::Bus;
+use recon_bus::{
+    AccessPolicy, Bus, FilterList, Persistence, PersistenceHandle,
+    ipc::{self, IpcBridge},
+    ws::{self, WsGateway},
+};
 
 fn main() -> iced::Result {
     utils::attach();
@@ -21,9 +26,49 @@ fn main() -> iced::Result {
         .run()
 }
 
+/// Serve the bus to external tools on its own thread for the lifetime of
+/// the app. The WebSocket gateway for overlays only runs when
+/// `RECON_WS_TOKEN` is set.
+fn spawn_gateways(bus: &Bus) {
+    let bridge = IpcBridge::new(bus);
+    let gateway = std::env::var("RECON_WS_TOKEN").ok().map(|token| {
+        WsGateway::new(bus, token)
+            .allow_publish("overlay/**")
+            .expect("valid overlay filter")
+    });
+    let spawned = std::thread::Builder::new()
+        .name("recon-gateways".into())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to build gateway runtime");
+            runtime.block_on(async {
+                let ipc = async {
+                    if let Err(e) = bridge.serve(ipc::default_endpoint()).await {
+                        tracing::warn!("IPC bridge stopped: {e}");
+                    }
+                };
+                let ws = async {
+                    if let Some(gateway) = gateway
+                        && let Err(e) = gateway.serve(ws::DEFAULT_ADDR).await
+                    {
+                        tracing::warn!("WebSocket gateway stopped: {e}");
+                    }
+                };
+                tokio::join!(ipc, ws);
+            });
+        });
+    if let Err(e) = spawned {
+        tracing::warn!("failed to start gateways: {e}");
+    }
+}
+
 struct Recon {
     main_window: window::Id,
+    bus: Bus,
     plugins: ReconPluginManager,
+    persistence: PersistenceHandle,
 }
 
 #[derive(Debug, Clone)]
@@ -41,15 +86,38 @@ impl Recon {
         });
 
         let bus = Bus::new();
-        let mut plugins = ReconPluginManager::new(bus).expect("failed to create plugin manager");
+        let persistence = Persistence::new(utils::data_dir().join("bus_state.json"))
+            .filter("settings/**")
+            .and_then(|p| p.filter("profile/**"))
+            .expect("valid persistence filters");
+        // Restore before any plugin subscribes so they are seeded with it.
+        if let Err(e) = persistence.restore(&bus) {
+            tracing::warn!("failed to restore bus state: {e}");
+        }
+        let persistence = persistence.start(&bus);
+        spawn_gateways(&bus);
+        let mut plugins =
+            ReconPluginManager::new(bus.clone()).expect("failed to create plugin manager");
+        let test_policy = AccessPolicy {
+            publish: FilterList::deny_all()
+                .allow("test/**")
+                .expect("valid test plugin filter"),
+            subscribe: FilterList::allow_all(),
+        };
         plugins
-            .add_plugin_from_file("test", "target/wasm32-wasip2/release/test_plugin.wasm")
+            .add_plugin_from_file(
+                "test",
+                "target/wasm32-wasip2/release/test_plugin.wasm",
+                test_policy,
+            )
             .expect("failed to load test plugin");
 
         (
             Self {
                 main_window: id,
+                bus,
                 plugins,
+                persistence,
             },
             open.map(|_| Message::WindowOpened),
         )
@@ -57,8 +125,20 @@ impl Recon {
 
     fn update(&mut self, message: Message) -> iced::Task<Message> {
         match message {
-            Message::WindowOpened => iced::Task::none(),
-            Message::WindowClosed(id) if id == self.main_window => iced::exit(),
+            Message::WindowOpened => {
+                system::window_opened(&self.bus);
+                iced::Task::none()
+            }
+            Message::WindowClosed(id) if id == self.main_window => {
+                system::window_closed(&self.bus);
+                for plugin in self.plugins.ids() {
+                    let _ = self.plugins.remove_plugin(&plugin);
+                }
+                if let Err(e) = self.persistence.flush() {
+                    tracing::warn!("failed to save bus state: {e}");
+                }
+                iced::exit()
+            }
             Message::WindowClosed(_) => iced::Task::none(),
             Message::Plugin(id, msg) => {
                 if let Err(e) = self.plugins.plugin_update(&id, msg) {
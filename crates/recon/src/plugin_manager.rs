@@ -1,14 +1,17 @@
-use std::{collections::HashMap, ops::DerefMut, path::Path};
+use std::{cell::RefCell, collections::HashMap, ops::DerefMut, path::Path};
 
 use igloo::widgets::{Message, ToElement, WrapperRenderer, WrapperTheme};
-use recon_bus::{Bus, host::EventBusView};
+use recon_bus::{AccessPolicy, Bus, Principal, host::EventBusView};
 use wasmtime::{
     Config, Engine, Store,
     component::{Component, HasSelf, Linker},
 };
 use wasmtime_wasi::{WasiCtxBuilder, p2::add_to_linker_sync};
 
-use crate::bindings::{ReconApp, ReconState};
+use crate::{
+    bindings::{ReconApp, ReconState},
+    system,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum PluginError {
@@ -20,11 +23,25 @@ pub enum PluginError {
 
 type Result<T> = std::result::Result<T, PluginError>;
 
+/// A loaded plugin and the store it runs in.
+///
+/// Each plugin has its own store, so it talks to the bus as its own
+/// [`Principal`], and dropping it releases everything it held. That
+/// includes the producers behind its bus streams, so its subscriptions end
+/// with it. Plugins are dropped before `$sys` announces they are gone, so
+/// `$sys/bus/subscribers` no longer counts them.
+struct Plugin {
+    app: ReconApp,
+    store: RefCell<Store<ReconState>>,
+}
+
 pub struct ReconPluginManager {
-    store: std::cell::RefCell<Store<ReconState>>,
     engine: Engine,
     linker: Linker<ReconState>,
-    plugins: HashMap<String, ReconApp>,
+    /// In a `RefCell` so a plugin that traps while drawing its view can be
+    /// unloaded, as `view` only has shared access.
+    plugins: RefCell<HashMap<String, Plugin>>,
+    bus: Bus,
 }
 
 impl ReconPluginManager {
@@ -42,49 +59,90 @@ impl ReconPluginManager {
         // Register WASI functions
         add_to_linker_sync(&mut linker)?;
 
-        let wasi_ctx = WasiCtxBuilder::new()
-            .inherit_stderr()
-            .inherit_stdout()
-            .build();
-
-        let store = Store::new(&engine, ReconState::new(wasi_ctx, bus));
-
         Ok(Self {
-            store: std::cell::RefCell::new(store),
             engine,
             linker,
-            plugins: HashMap::new(),
+            plugins: RefCell::default(),
+            bus,
         })
     }
 
     pub fn ids(&self) -> Vec<String> {
-        self.plugins.keys().cloned().collect()
+        self.plugins.borrow().keys().cloned().collect()
     }
 
+    /// Load a plugin that may use the bus as far as `policy` allows. Its
+    /// publishes carry `name` as the publisher.
     pub fn add_plugin_from_file(
         &mut self,
         name: impl Into<String>,
         file: impl AsRef<Path>,
+        policy: AccessPolicy,
     ) -> Result<()> {
         let name = name.into();
         let component = Component::from_file(&self.engine, file)?;
-        let app = ReconApp::instantiate(self.store.get_mut(), &component, &self.linker)?;
-        if self.plugins.insert(name.clone(), app).is_some() {
+        let wasi_ctx = WasiCtxBuilder::new()
+            .inherit_stderr()
+            .inherit_stdout()
+            .build();
+        let principal = Principal::new(name.as_str(), policy);
+        let mut store = Store::new(
+            &self.engine,
+            ReconState::new(wasi_ctx, self.bus.clone(), principal),
+        );
+        let app = ReconApp::instantiate(&mut store, &component, &self.linker)?;
+        let plugin = Plugin {
+            app,
+            store: RefCell::new(store),
+        };
+        if let Some(replaced) = self.plugins.get_mut().insert(name.clone(), plugin) {
+            drop(replaced);
             tracing::info!("Replaced existing plugin: {}", name);
+            system::plugin_unloaded(&self.bus, &name);
         }
+        system::plugin_loaded(&self.bus, &name);
+        system::subscribers(&self.bus);
+        Ok(())
+    }
+
+    pub fn remove_plugin(&mut self, id: &str) -> Result<()> {
+        let plugin = self
+            .plugins
+            .get_mut()
+            .remove(id)
+            .ok_or_else(|| PluginError::NotFound(id.into()))?;
+        drop(plugin);
+        system::plugin_unloaded(&self.bus, id);
+        system::subscribers(&self.bus);
         Ok(())
     }
 
+    /// Forward a message to a plugin. A plugin that traps is unloaded.
     pub fn plugin_update(&mut self, id: &str, msg: Message) -> Result<()> {
-        if let Some(plugin) = self.plugins.get_mut(id) {
-            let mut store = self.store.borrow_mut();
-            let Message { id, content } = msg;
-            Ok(plugin.call_update(store.deref_mut(), id, &content)?)
-        } else {
-            Err(PluginError::NotFound(id.into()))
+        let Some(plugin) = self.plugins.get_mut().get_mut(id) else {
+            return Err(PluginError::NotFound(id.into()));
+        };
+        let Message {
+            id: widget,
+            content,
+        } = msg;
+        let result = plugin
+            .app
+            .call_update(plugin.store.get_mut(), widget, &content);
+        if let Err(e) = &result {
+            self.crashed(id, e);
         }
+        Ok(result?)
     }
 
+    /// Unload a plugin that trapped and announce the crash.
+    fn crashed(&self, id: &str, error: &wasmtime::Error) {
+        drop(self.plugins.borrow_mut().remove(id));
+        system::plugin_crashed(&self.bus, id, &error.to_string());
+        system::subscribers(&self.bus);
+    }
+
+    /// Draw a plugin's view. A plugin that traps is unloaded.
     pub fn plugin_view<'a, Theme, Renderer>(
         &self,
         id: &str,
@@ -93,14 +151,19 @@ impl ReconPluginManager {
         Theme: WrapperTheme + 'a,
         Renderer: WrapperRenderer + 'a,
     {
-        let plugin = self.plugins.get(id)?;
-        let mut store = self.store.borrow_mut();
-        let result = plugin
-            .call_view(store.deref_mut())
-            .inspect_err(|e| {
+        let plugins = self.plugins.borrow();
+        let plugin = plugins.get(id)?;
+        let mut store = plugin.store.borrow_mut();
+        let result = match plugin.app.call_view(store.deref_mut()) {
+            Ok(result) => result,
+            Err(e) => {
                 tracing::error!("Failed to call view for plugin {}: {}", id, e);
-            })
-            .ok()?;
+                drop(store);
+                drop(plugins);
+                self.crashed(id, &e);
+                return None;
+            }
+        };
         let element = store
             .data_mut()
             .table
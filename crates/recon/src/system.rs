@@ -0,0 +1,42 @@
+//! Host lifecycle events on the reserved `$sys` namespace.
+//!
+//! Plugins may subscribe to `$sys/**` but never publish there, so anything
+//! seen on these topics came from the host.
+
+use recon_bus::{Bus, SYSTEM_PREFIX};
+
+pub fn plugin_loaded(bus: &Bus, id: &str) {
+    emit(bus, &format!("plugins/{id}/loaded"), "");
+}
+
+pub fn plugin_unloaded(bus: &Bus, id: &str) {
+    emit(bus, &format!("plugins/{id}/unloaded"), "");
+}
+
+/// The plugin trapped and was unloaded. The payload is the error.
+pub fn plugin_crashed(bus: &Bus, id: &str, error: &str) {
+    emit(bus, &format!("plugins/{id}/crashed"), error);
+}
+
+pub fn window_opened(bus: &Bus) {
+    emit(bus, "window/opened", "");
+}
+
+pub fn window_closed(bus: &Bus) {
+    emit(bus, "window/closed", "");
+}
+
+/// Retain the number of live subscriptions on `$sys/bus/subscribers`.
+pub fn subscribers(bus: &Bus) {
+    let count: usize = bus.topics().iter().map(|f| f.subscribers).sum();
+    let topic = format!("{SYSTEM_PREFIX}/bus/subscribers");
+    if let Err(e) = bus.publish_retained(topic, count.to_string()) {
+        tracing::warn!("failed to publish subscriber count: {e}");
+    }
+}
+
+fn emit(bus: &Bus, path: &str, payload: &str) {
+    if let Err(e) = bus.publish(format!("{SYSTEM_PREFIX}/{path}"), payload) {
+        tracing::warn!("failed to publish {SYSTEM_PREFIX}/{path}: {e}");
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 /// On windows attach to console if there is one
 /// On other platforms do nothing
 pub(crate) fn attach() {
@@ -14,3 +16,25 @@ pub(crate) fn attach() {
         }
     }
 }
+
+/// Directory Recon keeps its state in, created if missing:
+/// `%APPDATA%\Recon` on Windows, and `$XDG_DATA_HOME/recon` (or
+/// `~/.local/share/recon`) elsewhere. Falls back to the working directory
+/// when neither is set.
+pub(crate) fn data_dir() -> PathBuf {
+    #[cfg(windows)]
+    let dir = std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Recon"));
+    #[cfg(not(windows))]
+    let dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|dir| dir.join("recon"));
+
+    let Some(dir) = dir else {
+        return PathBuf::new();
+    };
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        tracing::warn!("failed to create {}: {e}", dir.display());
+    }
+    dir
+}
@@ -0,0 +1,68 @@
+//! Publish throughput with and without concurrent subscription churn.
+//!
+//! Publishers route through an immutable trie snapshot, so the `churn`
+//! case should stay close to `idle` instead of stalling behind writers.
+//! `prepared` uses a `Publisher` handle, which skips topic parsing and
+//! the trie walk entirely.
+
+use std::{
+    hint::black_box,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use recon_bus::Bus;
+
+fn populated_bus() -> (Bus, Vec<recon_bus::Subscription>) {
+    let bus = Bus::new();
+    let subs = (0..100)
+        .map(|i| bus.subscribe(format!("game/{i}/**")).unwrap())
+        .chain([
+            bus.subscribe("game/*/health").unwrap(),
+            bus.subscribe("**").unwrap(),
+        ])
+        .collect();
+    (bus, subs)
+}
+
+fn publish(c: &mut Criterion) {
+    let mut group = c.benchmark_group("publish");
+
+    let (bus, _subs) = populated_bus();
+    group.bench_function("idle", |b| {
+        b.iter(|| bus.publish(black_box("game/42/health"), "100").unwrap());
+    });
+
+    let publisher = bus.publisher("game/42/health").unwrap();
+    group.bench_function("prepared", |b| {
+        b.iter(|| publisher.publish(black_box("100")).unwrap());
+    });
+
+    let (bus, _subs) = populated_bus();
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn = thread::spawn({
+        let bus = bus.clone();
+        let stop = Arc::clone(&stop);
+        move || {
+            let mut i = 0u64;
+            while !stop.load(Ordering::Relaxed) {
+                drop(bus.subscribe(format!("match/{i}/**")).unwrap());
+                i += 1;
+            }
+        }
+    });
+    group.bench_function("churn", |b| {
+        b.iter(|| bus.publish(black_box("game/42/health"), "100").unwrap());
+    });
+    stop.store(true, Ordering::Relaxed);
+    churn.join().unwrap();
+
+    group.finish();
+}
+
+criterion_group!(benches, publish);
+criterion_main!(benches);
@@ -0,0 +1,255 @@
+//! Per-principal topic access control.
+//!
+//! A [`Principal`] is the identity a plugin talks to the bus as. Its
+//! [`AccessPolicy`] holds separate allow/deny [`FilterList`]s for publishing
+//! and subscribing. Deny entries always win over allow entries.
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    rpc, share,
+    topic::{Topic, TopicError, filter_covers, filters_overlap, topic_matches},
+};
+
+/// Allow and deny filters for a single kind of access.
+///
+/// An empty list permits nothing.
+#[derive(Debug, Clone, Default)]
+pub struct FilterList {
+    allow: Vec<Topic>,
+    deny: Vec<Topic>,
+}
+
+impl FilterList {
+    /// A list that permits every topic.
+    pub fn allow_all() -> Self {
+        Self::default().allow("**").expect("'**' is a valid filter")
+    }
+
+    /// A list that permits nothing.
+    pub fn deny_all() -> Self {
+        Self::default()
+    }
+
+    /// Permit topics matching `filter`.
+    pub fn allow(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Self, TopicError> {
+        self.allow.push(filter.try_into()?);
+        Ok(self)
+    }
+
+    /// Forbid topics matching `filter`, even if an allow entry matches.
+    pub fn deny(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Self, TopicError> {
+        self.deny.push(filter.try_into()?);
+        Ok(self)
+    }
+
+    /// Whether publishing to the concrete `topic` is permitted.
+    pub fn permits_topic(&self, topic: &Topic) -> bool {
+        self.allow.iter().any(|f| topic_matches(f, topic))
+            && !self.deny.iter().any(|f| topic_matches(f, topic))
+    }
+
+    /// Whether subscribing with `filter` is permitted.
+    ///
+    /// The whole filter must fall inside an allow entry, and it must not be
+    /// able to match any topic a deny entry matches.
+    pub fn permits_filter(&self, filter: &Topic) -> bool {
+        self.allow.iter().any(|f| filter_covers(f, filter))
+            && !self.deny.iter().any(|f| filters_overlap(f, filter))
+    }
+}
+
+/// Publish and subscribe permissions of a [`Principal`].
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    pub publish: FilterList,
+    pub subscribe: FilterList,
+}
+
+impl AccessPolicy {
+    /// A policy that permits everything.
+    pub fn allow_all() -> Self {
+        Self {
+            publish: FilterList::allow_all(),
+            subscribe: FilterList::allow_all(),
+        }
+    }
+}
+
+/// An identity that publishes and subscribes through the bus.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    id: Arc<str>,
+    policy: AccessPolicy,
+}
+
+impl Principal {
+    pub fn new(id: impl Into<Arc<str>>, policy: AccessPolicy) -> Self {
+        Self {
+            id: id.into(),
+            policy,
+        }
+    }
+
+    pub fn id(&self) -> &Arc<str> {
+        &self.id
+    }
+
+    pub fn policy(&self) -> &AccessPolicy {
+        &self.policy
+    }
+
+    /// `$reply` topics are always denied, whatever the policy allows.
+    /// Requests are answered through [`Bus::reply`](crate::Bus::reply).
+    pub fn check_publish(&self, topic: &Topic) -> Result<(), PermissionError> {
+        if self.policy.publish.permits_topic(topic) && !topic.is_reply() {
+            Ok(())
+        } else {
+            Err(self.denied(Action::Publish, topic))
+        }
+    }
+
+    /// Shared filters are checked by the filter after `$share/<group>/`.
+    /// Filters that could match a `$reply` topic are always denied, so only
+    /// the requester sees the replies to its requests.
+    pub fn check_subscribe(&self, filter: &Topic) -> Result<(), PermissionError> {
+        let target = match share::split(filter) {
+            Ok(Some((_, inner))) => inner,
+            _ => filter.clone(),
+        };
+        if self.policy.subscribe.permits_filter(&target) && !rpc::reaches_replies(&target) {
+            Ok(())
+        } else {
+            Err(self.denied(Action::Subscribe, filter))
+        }
+    }
+
+    fn denied(&self, action: Action, topic: &Topic) -> PermissionError {
+        PermissionError {
+            principal: Arc::clone(&self.id),
+            action,
+            topic: topic.as_raw().clone(),
+        }
+    }
+}
+
+/// The kind of access that was denied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Publish,
+    Subscribe,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Publish => f.write_str("publish to"),
+            Self::Subscribe => f.write_str("subscribe to"),
+        }
+    }
+}
+
+/// A principal tried to access a topic its policy does not permit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionError {
+    pub principal: Arc<str>,
+    pub action: Action,
+    pub topic: Arc<str>,
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' may not {} '{}'",
+            self.principal, self.action, self.topic
+        )
+    }
+}
+
+impl std::error::Error for PermissionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(s: &str) -> Topic {
+        Topic::try_from(s).unwrap()
+    }
+
+    fn plugin() -> Principal {
+        Principal::new(
+            "overlay",
+            AccessPolicy {
+                publish: FilterList::default().allow("overlay/**").unwrap(),
+                subscribe: FilterList::default()
+                    .allow("game/**")
+                    .unwrap()
+                    .deny("game/*/private/**")
+                    .unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn publish_allow_list() {
+        let p = plugin();
+        assert!(p.check_publish(&topic("overlay/visible")).is_ok());
+
+        let err = p.check_publish(&topic("game/valorant/status")).unwrap_err();
+        assert_eq!(err.action, Action::Publish);
+        assert_eq!(&*err.principal, "overlay");
+    }
+
+    #[test]
+    fn subscribe_must_fit_allow_list() {
+        let p = plugin();
+        assert!(p.check_subscribe(&topic("game/*/status")).is_ok());
+        assert!(p.check_subscribe(&topic("**")).is_err());
+        assert!(p.check_subscribe(&topic("chat/**")).is_err());
+
+        // Shared subscriptions are judged by their inner filter.
+        assert!(
+            p.check_subscribe(&topic("$share/hud/game/*/status"))
+                .is_ok()
+        );
+        assert!(p.check_subscribe(&topic("$share/hud/chat/**")).is_err());
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let p = plugin();
+        assert!(
+            p.check_subscribe(&topic("game/apex/private/notes"))
+                .is_err()
+        );
+        // `game/**` could receive private topics, so it is denied too.
+        assert!(p.check_subscribe(&topic("game/**")).is_err());
+    }
+
+    #[test]
+    fn reply_topics_stay_private() {
+        let p = Principal::new("ipc", AccessPolicy::allow_all());
+        assert!(p.check_publish(&topic("game/apex/status")).is_ok());
+        let err = p.check_publish(&topic("$reply/1-2")).unwrap_err();
+        assert_eq!(err.action, Action::Publish);
+
+        assert!(p.check_subscribe(&topic("game/**")).is_ok());
+        for filter in ["**", "$reply/**", "*/*", "$share/spy/$reply/*"] {
+            assert!(p.check_subscribe(&topic(filter)).is_err(), "{filter}");
+        }
+    }
+
+    #[test]
+    fn empty_list_denies_everything() {
+        let p = Principal::new("nobody", AccessPolicy::default());
+        assert!(p.check_publish(&topic("a")).is_err());
+        assert!(p.check_subscribe(&topic("a")).is_err());
+    }
+}
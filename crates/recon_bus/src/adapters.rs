@@ -0,0 +1,192 @@
+//! Stream, blocking and callback adapters for [`Subscription`].
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    Bus, BusError, DeliveryMode, Envelope, Subscription, Topic, TopicError, expiry::ExpiryWatch,
+};
+
+impl Stream for Subscription {
+    type Item = Envelope;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+/// Unparks the thread blocked in [`Subscription::recv_blocking`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl Subscription {
+    /// Block the current thread until the next message arrives.
+    ///
+    /// For plain OS threads. Calling this from async code stalls the
+    /// executor; use [`Subscription::recv`] there instead.
+    pub fn recv_blocking(&mut self) -> Option<Envelope> {
+        self.park_until(None)
+    }
+
+    /// Like [`Subscription::recv_blocking`], but give up after `timeout`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Envelope> {
+        self.park_until(Some(Instant::now() + timeout))
+    }
+
+    fn park_until(&mut self, deadline: Option<Instant>) -> Option<Envelope> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(envelope) = self.poll_recv(&mut cx) {
+                return envelope;
+            }
+            if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                return None;
+            }
+            // Wake for the next expiry too, as no runtime timer may be armed.
+            let expiry = self.expiry.as_ref().and_then(ExpiryWatch::next_deadline);
+            match deadline.into_iter().chain(expiry).min() {
+                None => thread::park(),
+                Some(wake_at) => {
+                    thread::park_timeout(wake_at.saturating_duration_since(Instant::now()))
+                }
+            }
+        }
+    }
+}
+
+/// Handle to a running [`Bus::on`] callback. Dropping it unsubscribes.
+#[derive(Debug)]
+pub struct Listener {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Bus {
+    /// Call `callback` with every message matching `filter`.
+    ///
+    /// The subscription runs on a spawned tokio task, so this must be
+    /// called from within a runtime. Uses [`DeliveryMode::Latest`].
+    pub fn on<F>(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        callback: F,
+    ) -> Result<Listener, BusError>
+    where
+        F: FnMut(Envelope) + Send + 'static,
+    {
+        self.on_with(filter, DeliveryMode::Latest, callback)
+    }
+
+    /// [`Bus::on`] with an explicit delivery mode.
+    pub fn on_with<F>(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        mode: DeliveryMode,
+        mut callback: F,
+    ) -> Result<Listener, BusError>
+    where
+        F: FnMut(Envelope) + Send + 'static,
+    {
+        let mut sub = self.subscribe_with(filter, mode)?;
+        let task = tokio::spawn(async move {
+            while let Some(envelope) = sub.recv().await {
+                callback(envelope);
+            }
+        });
+        Ok(Listener { task })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PublishOptions;
+
+    #[tokio::test]
+    async fn subscription_is_a_stream() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("feed").unwrap();
+        bus.publish("feed", "1").unwrap();
+
+        let next = std::future::poll_fn(|cx| Pin::new(&mut sub).poll_next(cx)).await;
+        assert_eq!(next.unwrap().payload, "1");
+    }
+
+    #[test]
+    fn blocking_receive_from_thread() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe_with("hook", DeliveryMode::queue(8)).unwrap();
+        assert!(sub.recv_timeout(Duration::from_millis(10)).is_none());
+
+        let worker = thread::spawn(move || {
+            let first = sub.recv_blocking().unwrap();
+            let second = sub.recv_timeout(Duration::from_secs(1)).unwrap();
+            (first.payload, second.payload)
+        });
+        thread::sleep(Duration::from_millis(20));
+        bus.publish("hook", "down").unwrap();
+        bus.publish("hook", "up").unwrap();
+
+        let (first, second) = worker.join().unwrap();
+        assert_eq!(first, "down");
+        assert_eq!(second, "up");
+        assert_eq!(bus.stats().subscribers, 0);
+    }
+
+    #[test]
+    fn blocking_receive_reports_expiry_without_runtime() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("hp").unwrap().notify_expiry();
+        let ttl = PublishOptions::new().ttl(Duration::from_millis(20));
+        bus.publish_with("hp", "40", ttl).unwrap();
+
+        let fresh = sub.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(!fresh.is_expired());
+        let stale = sub.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(stale.payload, "40");
+        assert!(stale.is_expired());
+        assert!(sub.recv_timeout(Duration::from_millis(10)).is_none());
+    }
+
+    #[tokio::test]
+    async fn callback_listener() {
+        let bus = Bus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let listener = bus
+            .on_with("game/*/kill", DeliveryMode::queue(8), move |e| {
+                tx.send(e.payload).unwrap();
+            })
+            .unwrap();
+
+        bus.publish("game/apex/kill", "1").unwrap();
+        bus.publish("game/apex/kill", "2").unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "1");
+        assert_eq!(rx.recv().await.unwrap(), "2");
+
+        drop(listener);
+        tokio::task::yield_now().await;
+        assert_eq!(bus.stats().subscribers, 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Command-line client for the recon bus IPC bridge.
+//!
+//! ```text
+//! recon-bus [--endpoint PATH] pub [--retain] TOPIC PAYLOAD
+//! recon-bus [--endpoint PATH] tail FILTER...
+//! ```
+
+use std::{io, path::PathBuf, process::ExitCode};
+
+use recon_bus::{
+    JournalPayload,
+    ipc::{self, Frame, IpcClient},
+};
+
+const USAGE: &str = "usage: recon-bus [--endpoint PATH] pub [--retain] TOPIC PAYLOAD
+       recon-bus [--endpoint PATH] tail FILTER...";
+
+enum Command {
+    Pub {
+        topic: String,
+        payload: String,
+        retain: bool,
+    },
+    Tail {
+        filters: Vec<String>,
+    },
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Option<(PathBuf, Command)> {
+    let mut endpoint = ipc::default_endpoint();
+    let mut command = args.next()?;
+    if command == "--endpoint" {
+        endpoint = args.next()?.into();
+        command = args.next()?;
+    }
+    let rest: Vec<String> = args.collect();
+    let command = match command.as_str() {
+        "pub" => {
+            let retain = rest.first().is_some_and(|a| a == "--retain");
+            let [topic, payload] = &rest[retain as usize..] else {
+                return None;
+            };
+            Command::Pub {
+                topic: topic.clone(),
+                payload: payload.clone(),
+                retain,
+            }
+        }
+        "tail" if !rest.is_empty() => Command::Tail { filters: rest },
+        _ => return None,
+    };
+    Some((endpoint, command))
+}
+
+async fn run(endpoint: PathBuf, command: Command) -> io::Result<()> {
+    let mut client = IpcClient::connect(&endpoint)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", endpoint.display())))?;
+    match command {
+        Command::Pub {
+            topic,
+            payload,
+            retain,
+        } => client.publish(&topic, payload, retain).await,
+        Command::Tail { filters } => {
+            for filter in &filters {
+                client.subscribe(filter).await?;
+            }
+            while let Some(frame) = client.next().await? {
+                match frame {
+                    Frame::Msg {
+                        topic,
+                        payload: JournalPayload::Text(text),
+                        ..
+                    } => println!("{topic} {text}"),
+                    Frame::Msg {
+                        topic,
+                        payload: JournalPayload::Bytes(bytes),
+                        ..
+                    } => println!("{topic} <{} bytes>", bytes.len()),
+                    Frame::Error { message } => eprintln!("error: {message}"),
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let Some((endpoint, command)) = parse(std::env::args().skip(1)) else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build tokio runtime");
+    match runtime.block_on(run(endpoint, command)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("recon-bus: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
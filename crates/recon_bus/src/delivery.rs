@@ -0,0 +1,141 @@
+//! Per-subscriber delivery modes and the mailbox that implements them.
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+use crate::Envelope;
+
+/// How messages are buffered for a single subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// Keep only the most recent message. A slow subscriber skips
+    /// intermediate values.
+    #[default]
+    Latest,
+    /// Keep up to `capacity` messages in publish order.
+    ///
+    /// A `capacity` of zero is treated as one.
+    Queue { capacity: usize, overflow: Overflow },
+}
+
+impl DeliveryMode {
+    /// A queue of `capacity` messages that drops the oldest when full.
+    pub fn queue(capacity: usize) -> Self {
+        Self::Queue {
+            capacity,
+            overflow: Overflow::DropOldest,
+        }
+    }
+}
+
+/// What a full queue does with a newly published message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Evict the oldest queued message to make room.
+    #[default]
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+    /// Discard the new message and report
+    /// [`BusError::QueueFull`](crate::BusError::QueueFull) to the publisher.
+    Error,
+}
+
+/// Outcome of pushing a message into a [`Mailbox`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Push {
+    Accepted,
+    /// Accepted by evicting the oldest queued message.
+    Evicted,
+    Dropped,
+    Rejected,
+}
+
+impl Push {
+    /// Whether the message was queued.
+    pub fn accepted(self) -> bool {
+        matches!(self, Self::Accepted | Self::Evicted)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MailboxState {
+    queue: VecDeque<Envelope>,
+    waker: Option<Waker>,
+}
+
+/// Bounded single-consumer buffer shared between the bus and a `Subscription`.
+#[derive(Debug)]
+pub(crate) struct Mailbox {
+    mode: DeliveryMode,
+    state: Mutex<MailboxState>,
+}
+
+impl Mailbox {
+    pub fn new(mode: DeliveryMode) -> Self {
+        Self {
+            mode,
+            state: Mutex::new(MailboxState::default()),
+        }
+    }
+
+    pub fn push(&self, envelope: Envelope) -> Push {
+        let mut state = self.state.lock().expect("mailbox lock poisoned");
+
+        let push = match self.mode {
+            DeliveryMode::Latest => {
+                state.queue.clear();
+                state.queue.push_back(envelope);
+                Push::Accepted
+            }
+            DeliveryMode::Queue { capacity, overflow } => {
+                if state.queue.len() < capacity.max(1) {
+                    state.queue.push_back(envelope);
+                    Push::Accepted
+                } else {
+                    match overflow {
+                        Overflow::DropOldest => {
+                            state.queue.pop_front();
+                            state.queue.push_back(envelope);
+                            Push::Evicted
+                        }
+                        Overflow::DropNewest => Push::Dropped,
+                        Overflow::Error => Push::Rejected,
+                    }
+                }
+            }
+        };
+
+        let waker = if push.accepted() {
+            state.waker.take()
+        } else {
+            None
+        };
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        push
+    }
+
+    /// Take everything still queued.
+    pub fn drain(&self) -> Vec<Envelope> {
+        let mut state = self.state.lock().expect("mailbox lock poisoned");
+        state.queue.drain(..).collect()
+    }
+
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Envelope> {
+        let mut state = self.state.lock().expect("mailbox lock poisoned");
+        match state.queue.pop_front() {
+            Some(envelope) => Poll::Ready(envelope),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
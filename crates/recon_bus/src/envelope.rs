@@ -1,30 +1,197 @@
 //! Message envelope type carried through the bus.
 
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The body of a message, either UTF-8 text or raw bytes.
+///
+/// Both variants are reference counted, so cloning never copies the data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Payload {
+    Text(Arc<str>),
+    Bytes(Arc<[u8]>),
+}
+
+impl Payload {
+    /// The payload as text, or `None` for binary payloads.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Bytes(_) => None,
+        }
+    }
+
+    /// The raw bytes of the payload. Text payloads yield their UTF-8 bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Bytes(bytes) => bytes,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Text(_))
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<Arc<str>> for Payload {
+    fn from(text: Arc<str>) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<String> for Payload {
+    fn from(text: String) -> Self {
+        Self::Text(text.into())
+    }
+}
+
+impl From<&str> for Payload {
+    fn from(text: &str) -> Self {
+        Self::Text(text.into())
+    }
+}
+
+impl From<Arc<[u8]>> for Payload {
+    fn from(bytes: Arc<[u8]>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes.into())
+    }
+}
+
+impl PartialEq<str> for Payload {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == Some(other)
+    }
+}
+
+impl PartialEq<&str> for Payload {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == Some(*other)
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Bytes(bytes) => write!(f, "<{} bytes>", bytes.len()),
+        }
+    }
+}
 
 /// A message on the bus.
 ///
-/// Both `topic` and `payload` are `Arc<str>`, so cloning an envelope
-/// to fan out to multiple subscribers costs only two atomic increments.
+/// `topic` is an `Arc<str>` and `payload` wraps an `Arc`, so cloning an
+/// envelope to fan out to multiple subscribers costs only two atomic
+/// increments.
 #[derive(Debug, Clone)]
 pub struct Envelope {
     pub topic: Arc<str>,
-    pub payload: Arc<str>,
+    pub payload: Payload,
     pub timestamp: Instant,
+    /// Wall-clock time of the publish.
+    pub published_at: SystemTime,
+    /// Per-topic publish counter, starting at 1. A latest-value subscriber
+    /// skipped `b.sequence - a.sequence - 1` messages between two envelopes
+    /// `a` and `b` of the same topic. Zero for envelopes not built by a bus.
+    pub sequence: u64,
+    /// Identity of the publisher, if it supplied one.
+    pub publisher: Option<Arc<str>>,
+    /// Free-form metadata such as content type.
+    pub headers: Option<Arc<Headers>>,
+    /// Topic to publish the reply on, set when this envelope is a request.
+    pub reply_to: Option<Arc<str>>,
+    /// When the payload goes stale. Expired envelopes are hidden from
+    /// subscribers and retained snapshots.
+    pub expires_at: Option<Instant>,
 }
 
+/// String header map carried by an [`Envelope`].
+pub type Headers = BTreeMap<String, String>;
+
 impl Envelope {
-    pub fn new(topic: Arc<str>, payload: Arc<str>) -> Self {
+    pub fn new(topic: Arc<str>, payload: impl Into<Payload>) -> Self {
         Self {
             topic,
-            payload,
+            payload: payload.into(),
             timestamp: Instant::now(),
+            published_at: SystemTime::now(),
+            sequence: 0,
+            publisher: None,
+            headers: None,
+            reply_to: None,
+            expires_at: None,
         }
     }
 
+    /// Whether the envelope's TTL has run out.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Instant::now())
+    }
+
+    /// Look up a header value.
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.as_ref()?.get(key).map(String::as_str)
+    }
+
     /// Deserialize the JSON payload into `T`.
     #[cfg(feature = "serde")]
     pub fn deserialize<T: ::serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
-        serde_json::from_str(&self.payload)
+        serde_json::from_slice(self.payload.as_bytes())
+    }
+}
+
+/// Optional metadata for [`Bus::publish_with`](crate::Bus::publish_with).
+#[derive(Debug, Clone, Default)]
+pub struct PublishOptions {
+    pub publisher: Option<Arc<str>>,
+    pub headers: Headers,
+    /// Also store the envelope as the topic's retained state.
+    pub retain: bool,
+    /// How long the payload stays valid after publishing.
+    pub ttl: Option<Duration>,
+}
+
+impl PublishOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publisher(mut self, id: impl Into<Arc<str>>) -> Self {
+        self.publisher = Some(id.into());
+        self
+    }
+
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn retain(mut self) -> Self {
+        self.retain = true;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
     }
 }
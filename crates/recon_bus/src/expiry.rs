@@ -0,0 +1,79 @@
+//! Expiry notifications for subscriptions.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use tokio::time::Sleep;
+
+use crate::Envelope;
+
+/// Tracks the last envelope a subscription yielded per topic and fires once
+/// it expires.
+#[derive(Debug, Default)]
+pub(crate) struct ExpiryWatch {
+    live: HashMap<Arc<str>, Envelope>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl ExpiryWatch {
+    /// Record `envelope` as its topic's current value.
+    pub fn observe(&mut self, envelope: &Envelope) {
+        if envelope.expires_at.is_some() {
+            self.live
+                .insert(Arc::clone(&envelope.topic), envelope.clone());
+        } else {
+            self.live.remove(&envelope.topic);
+        }
+    }
+
+    pub fn is_live(&self, topic: &str) -> bool {
+        self.live.contains_key(topic)
+    }
+
+    /// When the next tracked envelope expires.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.live.values().filter_map(|e| e.expires_at).min()
+    }
+
+    /// Resolve with the next tracked envelope whose TTL has run out.
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<Envelope> {
+        loop {
+            let now = Instant::now();
+            let expired = self
+                .live
+                .iter()
+                .find(|(_, e)| e.expires_at.is_some_and(|at| at <= now))
+                .map(|(topic, _)| Arc::clone(topic));
+            if let Some(topic) = expired {
+                return Poll::Ready(self.live.remove(&topic).expect("topic is tracked"));
+            }
+
+            let Some(deadline) = self.next_deadline() else {
+                self.timer = None;
+                return Poll::Pending;
+            };
+            // Outside a runtime there is no tokio timer to arm; blocking
+            // receivers park until `next_deadline` themselves.
+            if tokio::runtime::Handle::try_current().is_err() {
+                self.timer = None;
+                return Poll::Pending;
+            }
+            let deadline = tokio::time::Instant::from_std(deadline);
+            let timer = self
+                .timer
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if timer.deadline() != deadline {
+                timer.as_mut().reset(deadline);
+            }
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
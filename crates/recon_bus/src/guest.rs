@@ -5,4 +5,25 @@ wit_bindgen::generate!({
     world: "bus-world",
 });
 
-pub use recon::event_bus::bus::{EventMessage, publish, subscribe};
+pub use recon::event_bus::bus::{
+    BusError, EventMessage, Payload, PublishOptions, SubscribeOptions, publish, publish_bytes,
+    publish_with, reply, request, serve, subscribe, subscribe_many, subscribe_with,
+};
+
+impl Payload {
+    /// The payload as text, or `None` for binary payloads.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Binary(_) => None,
+        }
+    }
+
+    /// The raw bytes of the payload. Text payloads yield their UTF-8 bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(bytes) => bytes,
+        }
+    }
+}
@@ -11,22 +11,33 @@
 //! Ref: <https://github.com/bytecodealliance/wasmtime/blob/v43.0.0/crates/wit-bindgen/src/config.rs#L77-L88>
 
 use std::{
+    collections::VecDeque,
     pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
     task::{Context, Poll},
+    time::{Duration, UNIX_EPOCH},
 };
 
+use futures_core::Stream;
 use wasmtime::{
     StoreContextMut,
     component::{Destination, HasData, StreamProducer, StreamReader, StreamResult, VecBuffer},
 };
 
-use crate::Subscription;
+use crate::{
+    BusError, DeliveryMode, Envelope, Overflow, Payload, Principal, PublishOptions, REPLY_PREFIX,
+    Subscription, SubscriptionExt, Topic,
+};
 
 wasmtime::component::bindgen!({
     path: "wit",
     world: "bus-world",
 });
 
+use recon::event_bus::bus::{
+    BusError as WitBusError, Payload as WitPayload, PublishOptions as WitPublishOptions,
+    SubscribeOptions,
+};
 pub use recon::event_bus::bus::{EventMessage, Host, HostWithStore};
 
 /// Marker type for the event bus host capability.
@@ -40,8 +51,72 @@ impl HasData for EventBus {
 }
 
 /// View into event bus state, projected from the store.
+///
+/// Every guest call is checked against `principal`'s access policy, and
+/// guest publishes carry its id as the envelope's publisher. `replies`
+/// belongs to the same guest, and holds the requests it may answer.
 pub struct EventBusCtx<'a> {
     pub bus: &'a crate::Bus,
+    pub principal: &'a Principal,
+    pub replies: &'a PendingReplies,
+}
+
+/// How many unanswered requests a guest may hold on to. The oldest is
+/// forgotten first, as its requester has most likely timed out.
+const MAX_PENDING_REPLIES: usize = 1024;
+
+/// Reply topics of requests a guest received through `serve` and has not
+/// answered yet. `reply` only publishes to these, so a guest cannot answer
+/// requests that were handed to another.
+///
+/// Give each guest its own; clones share the same set.
+#[derive(Debug, Clone, Default)]
+pub struct PendingReplies(Arc<Mutex<VecDeque<Arc<str>>>>);
+
+impl PendingReplies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn grant(&self, reply_to: &Arc<str>) {
+        let mut pending = self.lock();
+        if pending.len() == MAX_PENDING_REPLIES {
+            pending.pop_front();
+        }
+        pending.push_back(Arc::clone(reply_to));
+    }
+
+    /// Use up the grant for `reply_to`, returning whether there was one.
+    fn take(&self, reply_to: &str) -> bool {
+        let mut pending = self.lock();
+        let Some(index) = pending.iter().position(|topic| **topic == *reply_to) else {
+            return false;
+        };
+        pending.remove(index);
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Arc<str>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl EventBusCtx<'_> {
+    fn publish_topic(&self, topic: &str) -> Result<Topic, BusError> {
+        let topic = Topic::parse_external(topic)?;
+        self.principal.check_publish(&topic)?;
+        Ok(topic)
+    }
+
+    fn options(&self) -> PublishOptions {
+        PublishOptions::new().publisher(self.principal.id().clone())
+    }
+
+    fn subscribe_filter(&self, filter: &str) -> Result<Topic, BusError> {
+        let filter = Topic::try_from(filter)?;
+        self.principal.check_subscribe(&filter)?;
+        Ok(filter)
+    }
 }
 
 /// Implement this on your store data type to provide event bus access.
@@ -50,11 +125,45 @@ pub trait EventBusView: Send {
 }
 
 impl Host for EventBusCtx<'_> {
-    fn publish(&mut self, topic: String, payload: String) -> Result<u64, String> {
-        match self.bus.publish(&*topic, payload) {
-            Ok(count) => Ok(count as u64),
-            Err(e) => Err(e.to_string()),
+    fn publish(&mut self, topic: String, payload: String) -> Result<u64, WitBusError> {
+        let topic = self.publish_topic(&topic)?;
+        Ok(self.bus.publish_with(&topic, payload, self.options())? as u64)
+    }
+
+    fn publish_bytes(&mut self, topic: String, payload: Vec<u8>) -> Result<u64, WitBusError> {
+        let topic = self.publish_topic(&topic)?;
+        Ok(self.bus.publish_with(&topic, payload, self.options())? as u64)
+    }
+
+    fn publish_with(
+        &mut self,
+        topic: String,
+        payload: WitPayload,
+        wit_options: WitPublishOptions,
+    ) -> Result<u64, WitBusError> {
+        let topic = self.publish_topic(&topic)?;
+        let mut options = self.options();
+        options.headers.extend(wit_options.headers);
+        options.ttl = wit_options.ttl_ms.map(Duration::from_millis);
+        options.retain = wit_options.retain;
+        Ok(self.bus.publish_with(&topic, payload, options)? as u64)
+    }
+
+    /// Replies bypass the publish policy: reply topics are generated by the
+    /// bus, and each can only be answered once, by the guest whose `serve`
+    /// stream handed it the request.
+    fn reply(&mut self, reply_to: String, payload: String) -> Result<u64, WitBusError> {
+        if !reply_to.starts_with(&format!("{REPLY_PREFIX}/")) {
+            return Err(WitBusError::InvalidTopic(format!(
+                "'{reply_to}' is not a reply topic"
+            )));
+        }
+        if !self.replies.take(&reply_to) {
+            return Err(WitBusError::PermissionDenied(format!(
+                "'{reply_to}' is not a request this plugin received"
+            )));
         }
+        Ok(self.bus.publish_with(&*reply_to, payload, self.options())? as u64)
     }
 }
 
@@ -62,15 +171,157 @@ impl HostWithStore for EventBus {
     async fn subscribe<S: Send>(
         accessor: &wasmtime::component::Accessor<S, Self>,
         filter: String,
-    ) -> Result<StreamReader<EventMessage>, String> {
+    ) -> Result<StreamReader<EventMessage>, WitBusError> {
+        accessor.with(|mut access| {
+            let ctx = access.get();
+            let filter = ctx.subscribe_filter(&filter)?;
+            let sub = ctx.bus.subscribe(&filter)?;
+            subscribe_stream(&mut access, sub).map_err(|e| WitBusError::Other(e.to_string()))
+        })
+    }
+
+    async fn subscribe_with<S: Send>(
+        accessor: &wasmtime::component::Accessor<S, Self>,
+        filter: String,
+        options: SubscribeOptions,
+    ) -> Result<StreamReader<EventMessage>, WitBusError> {
+        let periods = [options.debounce_ms, options.throttle_ms, options.sample_ms];
+        if periods.contains(&Some(0)) {
+            return Err(WitBusError::Other(
+                "debounce-ms, throttle-ms and sample-ms must be positive".into(),
+            ));
+        }
+        accessor.with(|mut access| {
+            let ctx = access.get();
+            let filter = ctx.subscribe_filter(&filter)?;
+            let mut sub = match options.predicate {
+                Some(predicate) => ctx.bus.subscribe_where(&filter, predicate.as_str())?,
+                None => ctx.bus.subscribe(&filter)?,
+            };
+            if options.notify_expiry {
+                sub = sub.notify_expiry();
+            }
+            let producer = SubscriptionProducer::with_options(sub, &options);
+            StreamReader::new(&mut access, producer).map_err(|e| WitBusError::Other(e.to_string()))
+        })
+    }
+
+    async fn subscribe_many<S: Send>(
+        accessor: &wasmtime::component::Accessor<S, Self>,
+        include: Vec<String>,
+        exclude: Vec<String>,
+    ) -> Result<StreamReader<EventMessage>, WitBusError> {
+        accessor.with(|mut access| {
+            let ctx = access.get();
+            let includes = include
+                .iter()
+                .map(|filter| ctx.subscribe_filter(filter))
+                .collect::<Result<Vec<_>, _>>()?;
+            // Exclusions only narrow the subscription, so they need no check.
+            let sub = ctx.bus.subscribe_many(
+                &includes,
+                exclude.iter().map(String::as_str),
+                DeliveryMode::Latest,
+            )?;
+            subscribe_stream(&mut access, sub).map_err(|e| WitBusError::Other(e.to_string()))
+        })
+    }
+
+    async fn request<S: Send>(
+        accessor: &wasmtime::component::Accessor<S, Self>,
+        topic: String,
+        payload: String,
+        timeout_ms: u64,
+    ) -> Result<EventMessage, WitBusError> {
+        let (bus, topic) = accessor.with(|mut access| {
+            let ctx = access.get();
+            ctx.publish_topic(&topic)
+                .map(|topic| (ctx.bus.clone(), topic))
+        })?;
+        let envelope = bus
+            .request(&topic, payload, Duration::from_millis(timeout_ms))
+            .await?;
+        Ok(EventMessage::from(&envelope))
+    }
+
+    async fn serve<S: Send>(
+        accessor: &wasmtime::component::Accessor<S, Self>,
+        filter: String,
+    ) -> Result<StreamReader<EventMessage>, WitBusError> {
         accessor.with(|mut access| {
-            let bus = access.get().bus;
-            let sub = bus.subscribe(&*filter).map_err(|e| e.to_string())?;
-            subscribe_stream(&mut access, sub).map_err(|e| e.to_string())
+            let ctx = access.get();
+            let filter = ctx.subscribe_filter(&filter)?;
+            let sub = ctx.bus.subscribe_with(
+                &filter,
+                DeliveryMode::Queue {
+                    capacity: crate::rpc::RESPONDER_QUEUE,
+                    overflow: Overflow::Error,
+                },
+            )?;
+            let producer = SubscriptionProducer::requests(sub, ctx.replies.clone());
+            StreamReader::new(&mut access, producer).map_err(|e| WitBusError::Other(e.to_string()))
         })
     }
 }
 
+impl From<BusError> for WitBusError {
+    fn from(e: BusError) -> Self {
+        match e {
+            BusError::Topic(e) => Self::InvalidTopic(e.to_string()),
+            BusError::Permission(e) => Self::PermissionDenied(e.to_string()),
+            BusError::QueueFull(n) => Self::QueueFull(n as u64),
+            BusError::NoResponder => Self::NoResponder,
+            BusError::Timeout => Self::Timeout,
+            BusError::Predicate(e) => Self::InvalidPredicate(e.to_string()),
+            #[cfg(feature = "schema")]
+            BusError::Validation(e) => Self::ValidationFailed(e.to_string()),
+            e => Self::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<&Payload> for WitPayload {
+    fn from(payload: &Payload) -> Self {
+        match payload {
+            Payload::Text(text) => Self::Text(text.to_string()),
+            Payload::Bytes(bytes) => Self::Binary(bytes.to_vec()),
+        }
+    }
+}
+
+impl From<WitPayload> for Payload {
+    fn from(payload: WitPayload) -> Self {
+        match payload {
+            WitPayload::Text(text) => text.into(),
+            WitPayload::Binary(bytes) => bytes.into(),
+        }
+    }
+}
+
+impl From<&Envelope> for EventMessage {
+    fn from(envelope: &Envelope) -> Self {
+        let timestamp_ms = envelope
+            .published_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Self {
+            topic: envelope.topic.to_string(),
+            payload: WitPayload::from(&envelope.payload),
+            sequence: envelope.sequence,
+            timestamp_ms,
+            publisher: envelope.publisher.as_deref().map(str::to_string),
+            headers: envelope
+                .headers
+                .iter()
+                .flat_map(|h| h.iter())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            expired: envelope.is_expired(),
+            reply_to: envelope.reply_to.as_deref().map(str::to_string),
+        }
+    }
+}
+
 /// Create a `StreamReader<EventMessage>` from a `Subscription`.
 pub fn subscribe_stream<S: wasmtime::AsContextMut>(
     store: &mut S,
@@ -79,29 +330,57 @@ pub fn subscribe_stream<S: wasmtime::AsContextMut>(
     StreamReader::new(store, SubscriptionProducer::new(sub))
 }
 
-type WatchReceiver = tokio::sync::watch::Receiver<Option<crate::Envelope>>;
-type ChangedFut =
-    Pin<Box<dyn std::future::Future<Output = (WatchReceiver, Option<crate::Envelope>)> + Send>>;
+type Envelopes = Box<dyn Stream<Item = Envelope> + Send + Unpin>;
 
+/// Adapts a [`Subscription`] into a component-model stream.
+///
+/// Works the same for every [`DeliveryMode`](crate::DeliveryMode): each
+/// envelope the subscription yields becomes one stream item.
 struct SubscriptionProducer {
-    _sub: Subscription,
-    fut: ChangedFut,
-}
-
-fn make_changed_fut(mut receiver: WatchReceiver) -> ChangedFut {
-    Box::pin(async move {
-        let result = match receiver.changed().await {
-            Ok(()) => receiver.borrow_and_update().clone(),
-            Err(_) => None,
-        };
-        (receiver, result)
-    })
+    envelopes: Envelopes,
+    /// Set for `serve` streams, which only yield requests and grant the
+    /// guest their reply topics.
+    requests: Option<PendingReplies>,
 }
 
 impl SubscriptionProducer {
     fn new(sub: Subscription) -> Self {
-        let fut = make_changed_fut(sub.clone_receiver());
-        Self { _sub: sub, fut }
+        Self {
+            envelopes: Box::new(sub),
+            requests: None,
+        }
+    }
+
+    /// Apply the rate controls in `options` on the host, so the guest is
+    /// only woken for messages it will use.
+    fn with_options(sub: Subscription, options: &SubscribeOptions) -> Self {
+        let ms = Duration::from_millis;
+        let mut envelopes: Envelopes = Box::new(sub);
+        if options.distinct {
+            envelopes = Box::new(envelopes.distinct());
+        }
+        if let Some(quiet) = options.debounce_ms {
+            envelopes = Box::new(envelopes.debounce(ms(quiet)));
+        }
+        if let Some(interval) = options.throttle_ms {
+            envelopes = Box::new(envelopes.throttle(ms(interval)));
+        }
+        if let Some(period) = options.sample_ms {
+            envelopes = Box::new(envelopes.sample(ms(period)));
+        }
+        Self {
+            envelopes,
+            requests: None,
+        }
+    }
+
+    /// Only yield envelopes that carry a reply topic, granting each to
+    /// `replies` as it is handed over.
+    fn requests(sub: Subscription, replies: PendingReplies) -> Self {
+        Self {
+            envelopes: Box::new(sub),
+            requests: Some(replies),
+        }
     }
 }
 
@@ -122,18 +401,93 @@ impl<D> StreamProducer<D> for SubscriptionProducer {
 
         let this = self.get_mut();
 
-        match this.fut.as_mut().poll(cx) {
-            Poll::Ready((receiver, Some(envelope))) => {
-                let msg = EventMessage {
-                    topic: envelope.topic.to_string(),
-                    payload: envelope.payload.to_string(),
-                };
-                dst.set_buffer(vec![msg].into());
-                this.fut = make_changed_fut(receiver);
-                Poll::Ready(Ok(StreamResult::Completed))
+        loop {
+            match Pin::new(&mut this.envelopes).poll_next(cx) {
+                Poll::Ready(Some(envelope)) => {
+                    if let Some(replies) = &this.requests {
+                        let Some(reply_to) = &envelope.reply_to else {
+                            continue;
+                        };
+                        replies.grant(reply_to);
+                    }
+                    dst.set_buffer(vec![EventMessage::from(&envelope)].into());
+                    return Poll::Ready(Ok(StreamResult::Completed));
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(StreamResult::Dropped)),
+                Poll::Pending => return Poll::Pending,
             }
-            Poll::Ready((_, None)) => Poll::Ready(Ok(StreamResult::Dropped)),
-            Poll::Pending => Poll::Pending,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccessPolicy, Bus, FilterList};
+
+    const REPLY_TO: &str = "$reply/1-2";
+
+    #[test]
+    fn guests_cannot_forge_or_sniff_replies() {
+        let bus = Bus::new();
+        let policy = AccessPolicy {
+            publish: FilterList::deny_all().allow("game/**").unwrap(),
+            subscribe: FilterList::allow_all(),
+        };
+        let principal = Principal::new("plugin", policy);
+        let replies = PendingReplies::new();
+        let mut ctx = EventBusCtx {
+            bus: &bus,
+            principal: &principal,
+            replies: &replies,
+        };
+        let mut requester = bus
+            .subscribe_with(REPLY_TO, DeliveryMode::queue(1))
+            .unwrap();
+
+        assert!(matches!(
+            ctx.publish(REPLY_TO.into(), "forged".into()),
+            Err(WitBusError::InvalidTopic(_))
+        ));
+        assert!(matches!(
+            ctx.publish_bytes(REPLY_TO.into(), vec![1]),
+            Err(WitBusError::InvalidTopic(_))
+        ));
+        assert!(matches!(
+            ctx.reply(REPLY_TO.into(), "forged".into()),
+            Err(WitBusError::PermissionDenied(_))
+        ));
+        assert!(ctx.subscribe_filter("$reply/**").is_err());
+        assert!(requester.recv_timeout(Duration::ZERO).is_none());
+
+        replies.grant(&Arc::from(REPLY_TO));
+        assert_eq!(ctx.reply(REPLY_TO.into(), "real".into()).unwrap(), 1);
+        assert_eq!(
+            requester.recv_timeout(Duration::ZERO).unwrap().payload,
+            "real"
+        );
+    }
+
+    #[test]
+    fn allow_all_policy_does_not_cover_replies() {
+        let bus = Bus::new();
+        let principal = Principal::new("plugin", AccessPolicy::allow_all());
+        let replies = PendingReplies::new();
+        let mut ctx = EventBusCtx {
+            bus: &bus,
+            principal: &principal,
+            replies: &replies,
+        };
+
+        let options = WitPublishOptions {
+            headers: Vec::new(),
+            ttl_ms: None,
+            retain: false,
+        };
+        let payload = WitPayload::Text("forged".into());
+        assert!(ctx.publish_with(REPLY_TO.into(), payload, options).is_err());
+        assert!(ctx.publish_topic("game/apex/status").is_ok());
+        assert!(ctx.subscribe_filter("**").is_err());
+        assert!(ctx.subscribe_filter("game/**").is_ok());
+    }
+}
@@ -0,0 +1,544 @@
+//! Local IPC bridge for processes outside the host.
+//!
+//! The bridge listens on a Unix domain socket, or a named pipe on Windows,
+//! and speaks line-delimited JSON. Every line is one [`Frame`] tagged by
+//! `op`:
+//!
+//! ```text
+//! > {"op":"pub","topic":"stats/kills","text":"12"}
+//! > {"op":"pub","topic":"settings/theme","text":"dark","retain":true}
+//! > {"op":"sub","filter":"game/**"}
+//! < {"op":"msg","filter":"game/**","topic":"game/apex/status","text":"online","sequence":3}
+//! > {"op":"unsub","filter":"game/**"}
+//! < {"op":"error","message":"permission denied: ..."}
+//! ```
+//!
+//! Payloads use the same `text`/`bytes` keys as journals. Publishing is not
+//! acknowledged; a rejected frame is answered with an `error` frame.
+
+use std::{collections::HashMap, io, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::{
+    AccessPolicy, Bus, BusError, DeliveryMode, Envelope, JournalPayload, Payload, Principal,
+    PublishOptions, Topic,
+};
+
+/// How many envelopes each bridged subscription buffers before dropping
+/// the oldest.
+const SUBSCRIPTION_QUEUE: usize = 1024;
+
+/// How many outgoing frames a connection buffers before its subscriptions
+/// start queueing.
+const OUTGOING_FRAMES: usize = 256;
+
+/// One line of the bridge protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Frame {
+    /// Client to bridge: publish to a concrete topic.
+    Pub {
+        topic: String,
+        #[serde(flatten)]
+        payload: JournalPayload,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        retain: bool,
+    },
+    /// Client to bridge: start receiving messages matching `filter`.
+    Sub { filter: String },
+    /// Client to bridge: stop a subscription made with the same filter.
+    Unsub { filter: String },
+    /// Bridge to client: a message for the subscription to `filter`.
+    Msg {
+        filter: String,
+        topic: String,
+        #[serde(flatten)]
+        payload: JournalPayload,
+        sequence: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        publisher: Option<String>,
+    },
+    /// Bridge to client: the previous frame was rejected.
+    Error { message: String },
+}
+
+impl Frame {
+    fn msg(filter: &str, envelope: &Envelope) -> Self {
+        Self::Msg {
+            filter: filter.to_string(),
+            topic: envelope.topic.to_string(),
+            payload: envelope.payload.clone().into(),
+            sequence: envelope.sequence,
+            publisher: envelope.publisher.as_deref().map(str::to_string),
+        }
+    }
+}
+
+/// Where the bridge listens unless told otherwise: `recon-bus.sock` in
+/// `$XDG_RUNTIME_DIR` (or the temp directory) on Unix, and the pipe
+/// `\\.\pipe\recon-bus` on Windows.
+pub fn default_endpoint() -> std::path::PathBuf {
+    #[cfg(windows)]
+    {
+        r"\\.\pipe\recon-bus".into()
+    }
+    #[cfg(not(windows))]
+    {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("recon-bus.sock")
+    }
+}
+
+/// Serves the bus to external processes.
+///
+/// Every connection acts as the same [`Principal`], `ipc` with full access
+/// by default, which is also stamped as the publisher of bridged messages.
+pub struct IpcBridge {
+    bus: Bus,
+    principal: Principal,
+}
+
+impl IpcBridge {
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            bus: bus.clone(),
+            principal: Principal::new("ipc", AccessPolicy::allow_all()),
+        }
+    }
+
+    /// Restrict what connected clients may publish and subscribe to.
+    pub fn principal(mut self, principal: Principal) -> Self {
+        self.principal = principal;
+        self
+    }
+
+    /// Accept connections on the Unix socket at `endpoint` until an accept
+    /// fails. A stale socket file from an earlier run is replaced, but a
+    /// socket another bridge is still serving is not.
+    #[cfg(unix)]
+    pub async fn serve(self, endpoint: impl AsRef<Path>) -> io::Result<()> {
+        use tokio::net::{UnixListener, UnixStream};
+
+        let path = endpoint.as_ref();
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already being served", path.display()),
+            ));
+        }
+        if let Err(e) = std::fs::remove_file(path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            return Err(e);
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let bridge = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let bridge = Arc::clone(&bridge);
+            tokio::spawn(async move { bridge.serve_connection(stream).await });
+        }
+    }
+
+    /// Accept connections on the named pipe `endpoint` until creating a
+    /// pipe instance fails.
+    #[cfg(windows)]
+    pub async fn serve(self, endpoint: impl AsRef<Path>) -> io::Result<()> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let name = endpoint.as_ref();
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(name)?;
+        let bridge = Arc::new(self);
+        loop {
+            server.connect().await?;
+            let stream = std::mem::replace(&mut server, ServerOptions::new().create(name)?);
+            let bridge = Arc::clone(&bridge);
+            tokio::spawn(async move { bridge.serve_connection(stream).await });
+        }
+    }
+
+    /// Speak the protocol over an already connected stream until the client
+    /// disconnects. Its subscriptions end with it.
+    pub async fn serve_connection<S>(&self, stream: S) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read, mut write) = tokio::io::split(stream);
+        let (mut session, mut outgoing) = Session::new(&self.bus, self.principal.clone());
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                write.write_all(&encode(&frame)).await?;
+                if outgoing.is_empty() {
+                    write.flush().await?;
+                }
+            }
+            io::Result::Ok(())
+        });
+
+        let mut lines = BufReader::new(read).lines();
+        let result = loop {
+            match lines.next_line().await {
+                Ok(Some(line)) if session.receive(&line).await => {}
+                Ok(_) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        drop(session);
+        let written = writer.await.unwrap_or(Ok(()));
+        result.and(written)
+    }
+}
+
+/// One connected client: its subscriptions and the frames queued for it.
+///
+/// Shared by every transport that speaks [`Frame`]s. Dropping the session
+/// ends its subscriptions and closes the outgoing channel.
+pub(crate) struct Session {
+    bus: Bus,
+    principal: Principal,
+    tx: mpsc::Sender<Frame>,
+    subs: HashMap<String, JoinHandle<()>>,
+}
+
+impl Session {
+    /// A session and the receiver its outgoing frames arrive on.
+    pub(crate) fn new(bus: &Bus, principal: Principal) -> (Self, mpsc::Receiver<Frame>) {
+        let (tx, rx) = mpsc::channel(OUTGOING_FRAMES);
+        let session = Self {
+            bus: bus.clone(),
+            principal,
+            tx,
+            subs: HashMap::new(),
+        };
+        (session, rx)
+    }
+
+    /// Handle one incoming frame, answering with an `error` frame if it is
+    /// rejected. Returns `false` once the outgoing side is gone.
+    pub(crate) async fn receive(&mut self, text: &str) -> bool {
+        if text.trim().is_empty() {
+            return true;
+        }
+        let handled = serde_json::from_str(text)
+            .map_err(|e| e.to_string())
+            .and_then(|frame| self.handle(frame));
+        match handled {
+            Ok(()) => true,
+            Err(message) => self.tx.send(Frame::Error { message }).await.is_ok(),
+        }
+    }
+
+    fn handle(&mut self, frame: Frame) -> Result<(), String> {
+        match frame {
+            Frame::Pub {
+                topic,
+                payload,
+                retain,
+            } => self
+                .publish(&topic, payload.into(), retain)
+                .map_err(|e| e.to_string()),
+            Frame::Sub { filter } => {
+                if !self.subs.contains_key(&filter) {
+                    let task = self.forward(filter.clone()).map_err(|e| e.to_string())?;
+                    self.subs.insert(filter, task);
+                }
+                Ok(())
+            }
+            Frame::Unsub { filter } => {
+                if let Some(task) = self.subs.remove(&filter) {
+                    task.abort();
+                }
+                Ok(())
+            }
+            Frame::Msg { .. } | Frame::Error { .. } => {
+                Err("only pub, sub and unsub frames are accepted".into())
+            }
+        }
+    }
+
+    fn publish(&self, topic: &str, payload: Payload, retain: bool) -> Result<(), BusError> {
+        let topic = Topic::parse_external(topic)?;
+        self.principal.check_publish(&topic)?;
+        let mut options = PublishOptions::new().publisher(self.principal.id().clone());
+        options.retain = retain;
+        self.bus.publish_with(&topic, payload, options)?;
+        Ok(())
+    }
+
+    /// Subscribe to `filter` and send its messages to the client from a
+    /// spawned task.
+    fn forward(&self, filter: String) -> Result<JoinHandle<()>, BusError> {
+        let topic = Topic::try_from(filter.as_str())?;
+        self.principal.check_subscribe(&topic)?;
+        let mut sub = self
+            .bus
+            .subscribe_with(&topic, DeliveryMode::queue(SUBSCRIPTION_QUEUE))?;
+        let tx = self.tx.clone();
+        Ok(tokio::spawn(async move {
+            while let Some(envelope) = sub.recv().await {
+                if tx.send(Frame::msg(&filter, &envelope)).await.is_err() {
+                    break;
+                }
+            }
+        }))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.subs.values().for_each(JoinHandle::abort);
+    }
+}
+
+fn encode(frame: &Frame) -> Vec<u8> {
+    let mut line = serde_json::to_vec(frame).expect("frames always serialize");
+    line.push(b'\n');
+    line
+}
+
+type BoxedRead = Box<dyn AsyncRead + Send + Unpin>;
+type BoxedWrite = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Client side of the bridge protocol, used by the `recon-bus` CLI.
+pub struct IpcClient {
+    lines: Lines<BufReader<BoxedRead>>,
+    write: BoxedWrite,
+}
+
+impl IpcClient {
+    /// Connect to a bridge serving `endpoint`.
+    pub async fn connect(endpoint: impl AsRef<Path>) -> io::Result<Self> {
+        #[cfg(unix)]
+        let stream = tokio::net::UnixStream::connect(endpoint).await?;
+        #[cfg(windows)]
+        let stream =
+            tokio::net::windows::named_pipe::ClientOptions::new().open(endpoint.as_ref())?;
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Speak the protocol over an already connected stream.
+    pub fn from_stream(stream: impl AsyncRead + AsyncWrite + Send + 'static) -> Self {
+        let (read, write) = tokio::io::split(stream);
+        Self {
+            lines: BufReader::new(Box::new(read) as BoxedRead).lines(),
+            write: Box::new(write),
+        }
+    }
+
+    pub async fn send(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write.write_all(&encode(frame)).await?;
+        self.write.flush().await
+    }
+
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        payload: impl Into<Payload>,
+        retain: bool,
+    ) -> io::Result<()> {
+        self.send(&Frame::Pub {
+            topic: topic.to_string(),
+            payload: payload.into().into(),
+            retain,
+        })
+        .await
+    }
+
+    pub async fn subscribe(&mut self, filter: &str) -> io::Result<()> {
+        self.send(&Frame::Sub {
+            filter: filter.to_string(),
+        })
+        .await
+    }
+
+    pub async fn unsubscribe(&mut self, filter: &str) -> io::Result<()> {
+        self.send(&Frame::Unsub {
+            filter: filter.to_string(),
+        })
+        .await
+    }
+
+    /// The next frame from the bridge, or `None` once it disconnects.
+    pub async fn next(&mut self) -> io::Result<Option<Frame>> {
+        loop {
+            let Some(line) = self.lines.next_line().await? else {
+                return Ok(None);
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str(&line)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FilterList, TopicError};
+
+    fn connect(bridge: IpcBridge) -> IpcClient {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move { bridge.serve_connection(server).await });
+        IpcClient::from_stream(client)
+    }
+
+    #[test]
+    fn frame_encoding() {
+        let frame = Frame::Pub {
+            topic: "stats/kills".into(),
+            payload: JournalPayload::Text("12".into()),
+            retain: false,
+        };
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(json, r#"{"op":"pub","topic":"stats/kills","text":"12"}"#);
+        assert_eq!(serde_json::from_str::<Frame>(&json).unwrap(), frame);
+
+        let frame: Frame =
+            serde_json::from_str(r#"{"op":"pub","topic":"a","bytes":[1,2],"retain":true}"#)
+                .unwrap();
+        assert!(matches!(frame, Frame::Pub { retain: true, .. }));
+    }
+
+    #[tokio::test]
+    async fn publish_and_subscribe_over_a_stream() {
+        let bus = Bus::new();
+        let mut local = bus.subscribe("stats/*").unwrap();
+        let mut client = connect(IpcBridge::new(&bus));
+
+        client.publish("stats/kills", "12", false).await.unwrap();
+        let envelope = local.recv().await.unwrap();
+        assert_eq!(envelope.payload, "12");
+        assert_eq!(envelope.publisher.as_deref(), Some("ipc"));
+
+        client.subscribe("game/**").await.unwrap();
+        // Frames are handled in order, so once our own publish comes back
+        // the subscription is live.
+        client.publish("game/ping", "", false).await.unwrap();
+        let Some(Frame::Msg { .. }) = client.next().await.unwrap() else {
+            panic!("expected the bridged publish to come back");
+        };
+        bus.publish_bytes("game/apex/frame", vec![7]).unwrap();
+        let frame = client.next().await.unwrap().unwrap();
+        let Frame::Msg {
+            filter,
+            topic,
+            payload,
+            ..
+        } = frame
+        else {
+            panic!("expected a message, got {frame:?}");
+        };
+        assert_eq!(filter, "game/**");
+        assert_eq!(topic, "game/apex/frame");
+        assert_eq!(payload, JournalPayload::Bytes(vec![7]));
+
+        client.unsubscribe("game/**").await.unwrap();
+        client.subscribe("a//b").await.unwrap();
+        assert!(matches!(
+            client.next().await.unwrap(),
+            Some(Frame::Error { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_a_unix_socket() {
+        let path = std::env::temp_dir().join(format!("recon-bus-{}.sock", std::process::id()));
+        let bus = Bus::new();
+        let mut local = bus.subscribe("stats/kills").unwrap();
+        tokio::spawn(IpcBridge::new(&bus).serve(path.clone()));
+
+        let mut client = loop {
+            match IpcClient::connect(&path).await {
+                Ok(client) => break client,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        client.publish("stats/kills", "3", false).await.unwrap();
+        assert_eq!(local.recv().await.unwrap().payload, "3");
+
+        let err = IpcBridge::new(&bus).serve(&path).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_frames_and_denied_topics() {
+        let bus = Bus::new();
+        let policy = AccessPolicy {
+            publish: FilterList::deny_all().allow("stats/**").unwrap(),
+            subscribe: FilterList::deny_all(),
+        };
+        let mut client = connect(IpcBridge::new(&bus).principal(Principal::new("scraper", policy)));
+
+        for frame in [
+            Frame::Pub {
+                topic: "settings/theme".into(),
+                payload: JournalPayload::Text("dark".into()),
+                retain: true,
+            },
+            Frame::Sub {
+                filter: "**".into(),
+            },
+            Frame::Error {
+                message: "hi".into(),
+            },
+        ] {
+            client.send(&frame).await.unwrap();
+            assert!(matches!(
+                client.next().await.unwrap(),
+                Some(Frame::Error { .. })
+            ));
+        }
+
+        client.write.write_all(b"not json\n").await.unwrap();
+        assert!(matches!(
+            client.next().await.unwrap(),
+            Some(Frame::Error { .. })
+        ));
+        assert!(bus.retained("settings/**").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn system_topics_are_host_only() {
+        let bus = Bus::new();
+        let mut client = connect(IpcBridge::new(&bus));
+
+        client.subscribe("$sys/**").await.unwrap();
+        client
+            .publish("$sys/window/opened", "", false)
+            .await
+            .unwrap();
+        let Some(Frame::Error { message }) = client.next().await.unwrap() else {
+            panic!("expected the reserved publish to be refused");
+        };
+        assert_eq!(message, TopicError::Reserved.to_string());
+
+        client.publish("$reply/1-2", "forged", false).await.unwrap();
+        let Some(Frame::Error { message }) = client.next().await.unwrap() else {
+            panic!("expected the forged reply to be refused");
+        };
+        assert_eq!(message, TopicError::Reserved.to_string());
+
+        bus.publish("$sys/window/opened", "").unwrap();
+        let Some(Frame::Msg { topic, .. }) = client.next().await.unwrap() else {
+            panic!("expected the host's system event");
+        };
+        assert_eq!(topic, "$sys/window/opened");
+    }
+}
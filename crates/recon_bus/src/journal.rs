@@ -0,0 +1,372 @@
+//! Recording the bus to a JSON Lines journal and replaying it.
+//!
+//! Each line holds one envelope:
+//!
+//! ```text
+//! {"offset_us":1520,"topic":"game/valorant/status","text":"online"}
+//! {"offset_us":1984,"topic":"game/valorant/frame","bytes":[137,80,78,71]}
+//! ```
+//!
+//! `offset_us` is microseconds since the recording started.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Bus, BusError, DeliveryMode, Envelope, Payload, Subscription};
+
+/// How many envelopes the recorder buffers before dropping the oldest.
+const RECORDER_QUEUE: usize = 4096;
+
+/// How often an idle recorder checks whether it was stopped.
+const RECORDER_POLL: Duration = Duration::from_millis(50);
+
+/// One recorded envelope.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Microseconds since the recording started.
+    pub offset_us: u64,
+    pub topic: String,
+    #[serde(flatten)]
+    pub payload: JournalPayload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalPayload {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<Payload> for JournalPayload {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Text(text) => Self::Text(text.to_string()),
+            Payload::Bytes(bytes) => Self::Bytes(bytes.to_vec()),
+        }
+    }
+}
+
+impl From<JournalPayload> for Payload {
+    fn from(payload: JournalPayload) -> Self {
+        match payload {
+            JournalPayload::Text(text) => text.into(),
+            JournalPayload::Bytes(bytes) => bytes.into(),
+        }
+    }
+}
+
+impl JournalEntry {
+    fn new(envelope: &Envelope, start: Instant) -> Self {
+        let offset = envelope.timestamp.saturating_duration_since(start);
+        Self {
+            offset_us: offset.as_micros() as u64,
+            topic: envelope.topic.to_string(),
+            payload: envelope.payload.clone().into(),
+        }
+    }
+
+    fn offset(&self) -> Duration {
+        Duration::from_micros(self.offset_us)
+    }
+
+    fn publish(&self, bus: &Bus) -> Result<usize, BusError> {
+        match &self.payload {
+            JournalPayload::Text(text) => bus.publish(self.topic.as_str(), text.as_str()),
+            JournalPayload::Bytes(bytes) => bus.publish_bytes(self.topic.as_str(), bytes.clone()),
+        }
+    }
+}
+
+/// Writes every envelope on the bus to a journal from a background thread.
+///
+/// Subscribes to `**` with a queue, so bursts are buffered rather than
+/// collapsed. Dropping the recorder stops it and flushes the journal; use
+/// [`Recorder::stop`] to see write errors.
+pub struct Recorder {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<u64>>>,
+}
+
+impl Recorder {
+    /// Record into a new file at `path`, replacing any existing file.
+    pub fn create(bus: &Bus, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::to_writer(bus, File::create(path)?))
+    }
+
+    /// Record into any writer.
+    pub fn to_writer(bus: &Bus, writer: impl Write + Send + 'static) -> Self {
+        let sub = bus
+            .subscribe_with("**", DeliveryMode::queue(RECORDER_QUEUE))
+            .expect("'**' is a valid filter");
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new()
+            .name("recon-bus-recorder".into())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || record(sub, BufWriter::new(writer), &stop)
+            })
+            .expect("failed to spawn recorder thread");
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop recording, write out everything already queued, and return how
+    /// many entries were written.
+    pub fn stop(mut self) -> io::Result<u64> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> io::Result<u64> {
+        self.stop.store(true, Ordering::Relaxed);
+        let Some(thread) = self.thread.take() else {
+            return Ok(0);
+        };
+        thread.thread().unpark();
+        thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("recorder thread panicked")))
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn record(mut sub: Subscription, mut out: impl Write, stop: &AtomicBool) -> io::Result<u64> {
+    let start = Instant::now();
+    let mut written = 0;
+    loop {
+        match sub.recv_timeout(RECORDER_POLL) {
+            Some(envelope) => {
+                serde_json::to_writer(&mut out, &JournalEntry::new(&envelope, start))?;
+                out.write_all(b"\n")?;
+                written += 1;
+            }
+            None if stop.load(Ordering::Relaxed) => break,
+            None => out.flush()?,
+        }
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Republishes a recorded journal into a bus.
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    entries: Vec<JournalEntry>,
+    position: usize,
+}
+
+impl Replayer {
+    /// Load a journal file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a journal from any reader. Blank lines are skipped.
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            entries.push(entry);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    pub fn from_entries(entries: Vec<JournalEntry>) -> Self {
+        Self {
+            entries,
+            position: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Entries not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.entries.len() - self.position
+    }
+
+    /// Start over from the first entry.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    /// Publish the next entry immediately and return it, or `None` once the
+    /// journal is exhausted.
+    pub fn step(&mut self, bus: &Bus) -> Result<Option<&JournalEntry>, BusError> {
+        let Some(entry) = self.entries.get(self.position) else {
+            return Ok(None);
+        };
+        self.position += 1;
+        entry.publish(bus)?;
+        Ok(Some(entry))
+    }
+
+    /// Publish every remaining entry, keeping the recorded gaps between
+    /// them divided by `speed`.
+    ///
+    /// A `speed` of `1.0` replays in real time and `4.0` four times faster;
+    /// `f64::INFINITY` publishes back to back. Timing is relative to the
+    /// first remaining entry, so playback can resume after [`step`]s.
+    ///
+    /// # Panics
+    ///
+    /// If `speed` is not positive.
+    ///
+    /// [`step`]: Replayer::step
+    pub async fn play(&mut self, bus: &Bus, speed: f64) -> Result<(), BusError> {
+        let start = tokio::time::Instant::now();
+        let base = self.next_offset();
+        while let Some(delay) = self.delay(base, speed) {
+            tokio::time::sleep_until(start + delay).await;
+            self.step(bus)?;
+        }
+        Ok(())
+    }
+
+    /// [`Replayer::play`] for threads without an async runtime.
+    pub fn play_blocking(&mut self, bus: &Bus, speed: f64) -> Result<(), BusError> {
+        let start = Instant::now();
+        let base = self.next_offset();
+        while let Some(delay) = self.delay(base, speed) {
+            thread::sleep((start + delay).saturating_duration_since(Instant::now()));
+            self.step(bus)?;
+        }
+        Ok(())
+    }
+
+    fn next_offset(&self) -> Duration {
+        self.entries
+            .get(self.position)
+            .map_or(Duration::ZERO, JournalEntry::offset)
+    }
+
+    /// How long after `base` the next entry is due, scaled by `speed`.
+    fn delay(&self, base: Duration, speed: f64) -> Option<Duration> {
+        assert!(speed > 0.0, "replay speed must be positive");
+        let offset = self.entries.get(self.position)?.offset();
+        Some(offset.saturating_sub(base).div_f64(speed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A writer tests can read back after the recorder is done.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn records_every_envelope() {
+        let bus = Bus::new();
+        let out = Shared::default();
+        let recorder = Recorder::to_writer(&bus, out.clone());
+
+        bus.publish("game/apex/status", "online").unwrap();
+        bus.publish_bytes("game/apex/frame", vec![1, 2, 3]).unwrap();
+        bus.publish("game/apex/status", "offline").unwrap();
+        assert_eq!(recorder.stop().unwrap(), 3);
+
+        let journal = out.0.lock().unwrap().clone();
+        let replayer = Replayer::from_reader(journal.as_slice()).unwrap();
+        let entries = replayer.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].topic, "game/apex/status");
+        assert_eq!(entries[1].payload, JournalPayload::Bytes(vec![1, 2, 3]));
+        assert!(entries[0].offset_us <= entries[2].offset_us);
+        assert_eq!(bus.stats().subscribers, 0);
+    }
+
+    fn journal() -> Replayer {
+        let entry = |offset_us, text: &str| JournalEntry {
+            offset_us,
+            topic: "match/round".into(),
+            payload: JournalPayload::Text(text.into()),
+        };
+        Replayer::from_entries(vec![entry(0, "1"), entry(20_000, "2"), entry(40_000, "3")])
+    }
+
+    #[test]
+    fn step_by_step() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("match/*", DeliveryMode::queue(8))
+            .unwrap();
+        let mut replayer = journal();
+
+        let entry = replayer.step(&bus).unwrap().unwrap();
+        assert_eq!(entry.payload, JournalPayload::Text("1".into()));
+        assert_eq!(sub.recv_timeout(Duration::ZERO).unwrap().payload, "1");
+        assert_eq!(replayer.remaining(), 2);
+
+        replayer.play_blocking(&bus, f64::INFINITY).unwrap();
+        assert!(replayer.step(&bus).unwrap().is_none());
+        assert_eq!(sub.recv_timeout(Duration::ZERO).unwrap().payload, "2");
+        assert_eq!(sub.recv_timeout(Duration::ZERO).unwrap().payload, "3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn accelerated_playback_keeps_spacing() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("match/*", DeliveryMode::queue(8))
+            .unwrap();
+        let mut replayer = journal();
+
+        let start = tokio::time::Instant::now();
+        replayer.play(&bus, 2.0).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(20));
+
+        for payload in ["1", "2", "3"] {
+            assert_eq!(sub.recv().await.unwrap().payload, payload);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_journal() {
+        let err = Replayer::from_reader("{\"topic\":1}\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            Replayer::from_reader("\n\n".as_bytes())
+                .unwrap()
+                .entries()
+                .is_empty()
+        );
+    }
+}
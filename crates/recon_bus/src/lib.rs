@@ -1,37 +1,129 @@
 //! In-process async topic-based pub/sub event bus with wildcard matching.
 
+mod acl;
+mod adapters;
+mod delivery;
 mod envelope;
+mod expiry;
 #[cfg(feature = "guest")]
 pub mod guest;
 #[cfg(feature = "host")]
 pub mod host;
+#[cfg(feature = "ipc")]
+pub mod ipc;
+#[cfg(feature = "serde")]
+mod journal;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+mod operators;
+#[cfg(feature = "serde")]
+mod persist;
+#[cfg(feature = "serde")]
+mod predicate;
+mod publisher;
+mod rpc;
+#[cfg(feature = "schema")]
+mod schema;
+mod share;
+mod stats;
 mod topic;
 mod trie;
+#[cfg(feature = "serde")]
+mod typed;
+#[cfg(feature = "websocket")]
+pub mod ws;
 
-use std::sync::{
-    Arc, RwLock,
-    atomic::{AtomicU64, Ordering},
+use std::{
+    hash::RandomState,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
 };
 
+pub use acl::{AccessPolicy, Action, FilterList, PermissionError, Principal};
+pub use adapters::Listener;
 use dashmap::DashMap;
-pub use envelope::Envelope;
+pub use delivery::{DeliveryMode, Overflow};
+use delivery::{Mailbox, Push};
+pub use envelope::{Envelope, Headers, Payload, PublishOptions};
+use expiry::ExpiryWatch;
+#[cfg(feature = "serde")]
+pub use journal::{JournalEntry, JournalPayload, Recorder, Replayer};
+pub use operators::{Debounce, Distinct, Map, Sample, SubscriptionExt, Throttle};
+#[cfg(feature = "serde")]
+pub use persist::{Persistence, PersistenceHandle};
+#[cfg(feature = "serde")]
+use predicate::PayloadJson;
+#[cfg(feature = "serde")]
+pub use predicate::{Predicate, PredicateError};
+pub use publisher::Publisher;
+pub use rpc::{REPLY_PREFIX, Responder};
+#[cfg(feature = "schema")]
+use schema::SchemaRegistry;
+#[cfg(feature = "schema")]
+pub use schema::{CatalogSchema, CatalogTopic, SchemaCatalog, SchemaError, ValidationError};
+use share::ShareGroup;
+pub use share::{SHARE_PREFIX, ShareStrategy};
+use stats::BusCounters;
+pub use stats::{BusStats, FilterStats, TopicStats};
 use tokio::sync::watch;
-pub use topic::{Topic, TopicError, topic_matches};
-use trie::{SubscriberId, TopicTrie};
+pub use topic::{SYSTEM_PREFIX, Topic, TopicError, topic_matches};
+use trie::{Routes, SubscriberId};
+#[cfg(feature = "serde")]
+pub use typed::{TypedSubscription, TypedTopic};
 
 #[derive(Debug)]
 pub enum BusError {
     Topic(TopicError),
+    Permission(PermissionError),
+    /// A queued subscriber with [`Overflow::Error`] was full.
+    ///
+    /// Holds the number of subscribers that rejected the message. All
+    /// other matching subscribers still received it.
+    QueueFull(usize),
+    /// No subscriber matched a request topic.
+    NoResponder,
+    /// No reply arrived before the request timeout.
+    Timeout,
+    /// Tried to reply to an envelope without a reply topic.
+    NotARequest,
+    /// A typed publish named a topic outside its [`TypedTopic`] pattern.
+    #[cfg(feature = "serde")]
+    PatternMismatch(Arc<str>),
     #[cfg(feature = "serde")]
     Serialize(serde_json::Error),
+    #[cfg(feature = "serde")]
+    Predicate(PredicateError),
+    /// A payload did not conform to the schema registered for its topic.
+    #[cfg(feature = "schema")]
+    Validation(ValidationError),
+    #[cfg(feature = "schema")]
+    Schema(SchemaError),
 }
 
 impl std::fmt::Display for BusError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Topic(e) => write!(f, "{e}"),
+            Self::Permission(e) => write!(f, "{e}"),
+            Self::QueueFull(n) => write!(f, "queue full for {n} subscriber(s)"),
+            Self::NoResponder => write!(f, "no subscriber for request topic"),
+            Self::Timeout => write!(f, "request timed out"),
+            Self::NotARequest => write!(f, "envelope has no reply topic"),
+            #[cfg(feature = "serde")]
+            Self::PatternMismatch(topic) => {
+                write!(f, "'{topic}' does not match the typed topic pattern")
+            }
             #[cfg(feature = "serde")]
             Self::Serialize(e) => write!(f, "{e}"),
+            #[cfg(feature = "serde")]
+            Self::Predicate(e) => write!(f, "{e}"),
+            #[cfg(feature = "schema")]
+            Self::Validation(e) => write!(f, "{e}"),
+            #[cfg(feature = "schema")]
+            Self::Schema(e) => write!(f, "{e}"),
         }
     }
 }
@@ -44,6 +136,12 @@ impl From<TopicError> for BusError {
     }
 }
 
+impl From<PermissionError> for BusError {
+    fn from(e: PermissionError) -> Self {
+        Self::Permission(e)
+    }
+}
+
 #[cfg(feature = "serde")]
 impl From<serde_json::Error> for BusError {
     fn from(e: serde_json::Error) -> Self {
@@ -51,14 +149,88 @@ impl From<serde_json::Error> for BusError {
     }
 }
 
+#[cfg(feature = "serde")]
+impl From<PredicateError> for BusError {
+    fn from(e: PredicateError) -> Self {
+        Self::Predicate(e)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl From<ValidationError> for BusError {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl From<SchemaError> for BusError {
+    fn from(e: SchemaError) -> Self {
+        Self::Schema(e)
+    }
+}
+
 struct Subscriber {
     sender: watch::Sender<Option<Envelope>>,
+    mailbox: Arc<Mailbox>,
+    /// Only envelopes whose JSON payload satisfies this are offered.
+    #[cfg(feature = "serde")]
+    predicate: Option<Predicate>,
+    /// Set for members of a shared subscription.
+    group: Option<Arc<ShareGroup>>,
+    /// Topics matching any of these are never offered.
+    excludes: Vec<Topic>,
+}
+
+impl Subscriber {
+    fn excludes(&self, topic: &Topic) -> bool {
+        self.excludes.iter().any(|f| topic_matches(f, topic))
+    }
+
+    #[cfg(feature = "serde")]
+    fn wants(&self, json: &PayloadJson<'_>) -> bool {
+        self.predicate.as_ref().is_none_or(|p| json.matches(p))
+    }
+
+    fn offer(&self, envelope: &Envelope) -> Push {
+        let push = self.mailbox.push(envelope.clone());
+        if push.accepted() {
+            self.sender.send_replace(Some(envelope.clone()));
+        }
+        push
+    }
 }
 
 struct BusInner {
-    trie: RwLock<TopicTrie>,
+    routes: Routes,
     subscribers: DashMap<SubscriberId, Subscriber>,
+    retained: DashMap<Topic, Envelope>,
     next_id: AtomicU64,
+    next_request: AtomicU64,
+    /// Keys that make reply topics unguessable.
+    reply_keys: RandomState,
+    counters: BusCounters,
+    /// Shared subscription groups by `<group>/<filter>`.
+    groups: DashMap<Arc<str>, Arc<ShareGroup>>,
+    #[cfg(feature = "schema")]
+    schemas: SchemaRegistry,
+}
+
+impl BusInner {
+    /// Hand `envelope` to one member of a shared group. See
+    /// [`ShareGroup::deliver`].
+    fn offer_shared(&self, group: &ShareGroup, envelope: &Envelope) -> Option<Push> {
+        #[cfg(feature = "serde")]
+        let json = PayloadJson::new(&envelope.payload);
+        group.deliver(|id| {
+            let sub = self.subscribers.get(&id)?;
+            #[cfg(feature = "serde")]
+            if !sub.wants(&json) {
+                return None;
+            }
+            Some(sub.offer(envelope))
+        })
+    }
 }
 
 /// The event bus. Clone to share across threads.
@@ -71,9 +243,16 @@ impl Bus {
     pub fn new() -> Self {
         Self {
             inner: Arc::new(BusInner {
-                trie: RwLock::new(TopicTrie::new()),
+                routes: Routes::new(),
                 subscribers: DashMap::new(),
+                retained: DashMap::new(),
                 next_id: AtomicU64::new(0),
+                next_request: AtomicU64::new(0),
+                reply_keys: RandomState::new(),
+                counters: BusCounters::default(),
+                groups: DashMap::new(),
+                #[cfg(feature = "schema")]
+                schemas: SchemaRegistry::default(),
             }),
         }
     }
@@ -86,8 +265,8 @@ impl Bus {
     ) -> Result<usize, BusError> {
         let topic = topic.try_into()?;
         let payload: Arc<str> = Arc::from(payload.into());
-        let envelope = Envelope::new(topic.as_raw().clone(), payload);
-        Ok(self.deliver(&topic, envelope))
+        let envelope = self.envelope(&topic, payload)?;
+        self.deliver(&topic, envelope)
     }
 
     /// Publish a serializable value as JSON to a topic.
@@ -99,55 +278,407 @@ impl Bus {
     ) -> Result<usize, BusError> {
         let topic = topic.try_into()?;
         let payload: Arc<str> = Arc::from(serde_json::to_string(value)?);
-        let envelope = Envelope::new(topic.as_raw().clone(), payload);
-        Ok(self.deliver(&topic, envelope))
+        let envelope = self.envelope(&topic, payload)?;
+        self.deliver(&topic, envelope)
+    }
+
+    /// Publish a string payload and retain it as the topic's current state.
+    ///
+    /// Subscriptions created later are seeded with the retained envelope if
+    /// their filter matches. Publishing again replaces it.
+    pub fn publish_retained(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+        payload: impl Into<String>,
+    ) -> Result<usize, BusError> {
+        self.publish_with(topic, payload.into(), PublishOptions::new().retain())
+    }
+
+    /// Publish any payload with a publisher identity, headers, a TTL or
+    /// retention.
+    ///
+    /// A retained envelope with a TTL is dropped from the retained state
+    /// once it expires.
+    pub fn publish_with(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+        payload: impl Into<Payload>,
+        options: PublishOptions,
+    ) -> Result<usize, BusError> {
+        let topic = topic.try_into()?;
+        let mut envelope = self.envelope(&topic, payload)?;
+        envelope.publisher = options.publisher;
+        envelope.expires_at = options.ttl.map(|ttl| envelope.timestamp + ttl);
+        if !options.headers.is_empty() {
+            envelope.headers = Some(Arc::new(options.headers));
+        }
+        if options.retain {
+            self.inner.retained.insert(topic.clone(), envelope.clone());
+        }
+        self.deliver(&topic, envelope)
+    }
+
+    /// Drop the retained envelope for a concrete topic, returning it.
+    pub fn clear_retained(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Option<Envelope>, BusError> {
+        let topic = topic.try_into()?;
+        Ok(self.inner.retained.remove(&topic).map(|(_, e)| e))
     }
 
-    fn deliver(&self, topic: &Topic, envelope: Envelope) -> usize {
-        let matching = self
+    /// Snapshot every retained envelope matching `filter`, oldest first.
+    pub fn retained(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Vec<Envelope>, BusError> {
+        let filter = filter.try_into()?;
+        Ok(self.retained_matching(&filter))
+    }
+
+    /// Publish a binary payload to a topic.
+    pub fn publish_bytes(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+        payload: impl Into<Arc<[u8]>>,
+    ) -> Result<usize, BusError> {
+        let topic = topic.try_into()?;
+        let envelope = self.envelope(&topic, Payload::Bytes(payload.into()))?;
+        self.deliver(&topic, envelope)
+    }
+
+    /// Build an envelope for `topic`, stamped with its next sequence number.
+    ///
+    /// Every publish path goes through here, so this is also where payloads
+    /// are checked against registered schemas.
+    fn envelope(&self, topic: &Topic, payload: impl Into<Payload>) -> Result<Envelope, BusError> {
+        let mut envelope = Envelope::new(topic.as_raw().clone(), payload);
+        #[cfg(feature = "schema")]
+        self.inner.schemas.validate(topic, &envelope.payload)?;
+        envelope.sequence = self.inner.counters.next_sequence(topic.as_raw());
+        Ok(envelope)
+    }
+
+    /// Retained envelopes on topics `routed` selects that `subscriber`
+    /// accepts.
+    fn retained_for(
+        &self,
+        subscriber: &Subscriber,
+        routed: impl Fn(&Topic) -> bool,
+    ) -> Vec<Envelope> {
+        let envelopes = self.retained_where(|topic| routed(topic) && !subscriber.excludes(topic));
+        #[cfg(feature = "serde")]
+        let envelopes = envelopes
+            .into_iter()
+            .filter(|e| subscriber.wants(&PayloadJson::new(&e.payload)))
+            .collect();
+        #[cfg(not(feature = "serde"))]
+        let _ = subscriber;
+        envelopes
+    }
+
+    fn retained_matching(&self, filter: &Topic) -> Vec<Envelope> {
+        self.retained_where(|topic| topic_matches(filter, topic))
+    }
+
+    fn retained_where(&self, selected: impl Fn(&Topic) -> bool) -> Vec<Envelope> {
+        self.inner.retained.retain(|_, e| !e.is_expired());
+        let mut envelopes: Vec<Envelope> = self
             .inner
-            .trie
-            .read()
-            .expect("trie lock poisoned")
-            .matching(topic);
+            .retained
+            .iter()
+            .filter(|entry| selected(entry.key()))
+            .map(|entry| entry.value().clone())
+            .collect();
+        envelopes.sort_by_key(|e| e.timestamp);
+        envelopes
+    }
+
+    fn deliver(&self, topic: &Topic, envelope: Envelope) -> Result<usize, BusError> {
+        let matching = self.inner.routes.load().matching(topic);
+        self.deliver_to(topic, envelope, &matching)
+    }
 
+    fn deliver_to(
+        &self,
+        topic: &Topic,
+        envelope: Envelope,
+        matching: &[SubscriberId],
+    ) -> Result<usize, BusError> {
+        let mut matched = 0;
         let mut delivered = 0;
+        let mut dropped = 0;
+        let mut rejected = 0;
+        let mut tally = |push| {
+            matched += 1;
+            match push {
+                Push::Accepted => delivered += 1,
+                // Delivered, at the cost of an older queued envelope.
+                Push::Evicted => {
+                    delivered += 1;
+                    dropped += 1;
+                }
+                Push::Dropped => dropped += 1,
+                Push::Rejected => rejected += 1,
+            }
+        };
+        // Shared groups are collected here and offered the envelope once
+        // each, rather than once per member.
+        let mut groups: Vec<Arc<ShareGroup>> = Vec::new();
+        #[cfg(feature = "serde")]
+        let json = PayloadJson::new(&envelope.payload);
         matching.iter().for_each(|id| {
-            if let Some(sub) = self.inner.subscribers.get(id) {
-                let _ = sub.sender.send(Some(envelope.clone()));
-                delivered += 1;
+            let Some(sub) = self.inner.subscribers.get(id) else {
+                return;
+            };
+            if sub.excludes(topic) {
+                return;
+            }
+            if let Some(group) = &sub.group {
+                if !groups.iter().any(|g| Arc::ptr_eq(g, group)) {
+                    groups.push(Arc::clone(group));
+                }
+                return;
             }
+            #[cfg(feature = "serde")]
+            if !sub.wants(&json) {
+                return;
+            }
+            tally(sub.offer(&envelope));
         });
+        groups.iter().for_each(|group| {
+            if let Some(push) = self.inner.offer_shared(group, &envelope) {
+                tally(push);
+            }
+        });
+
+        self.inner
+            .counters
+            .record(topic.as_raw(), matched, delivered, dropped + rejected);
 
-        delivered
+        if rejected > 0 {
+            return Err(BusError::QueueFull(rejected));
+        }
+        Ok(delivered)
     }
 
     /// Subscribe to a topic pattern (may contain `*` and `**` wildcards).
+    ///
+    /// Uses [`DeliveryMode::Latest`].
     pub fn subscribe(
         &self,
         filter: impl TryInto<Topic, Error = TopicError>,
     ) -> Result<Subscription, BusError> {
+        self.subscribe_with(filter, DeliveryMode::Latest)
+    }
+
+    /// Subscribe to a topic pattern with an explicit delivery mode.
+    pub fn subscribe_with(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        mode: DeliveryMode,
+    ) -> Result<Subscription, BusError> {
+        self.register(vec![filter.try_into()?], Registration::new(mode))
+    }
+
+    /// Subscribe to every topic matching one of `includes` and none of
+    /// `excludes` with a single subscription.
+    ///
+    /// A topic matched by several includes is still delivered once. The
+    /// filters can be changed later with [`Subscription::add_filter`] and
+    /// [`Subscription::add_exclusion`].
+    ///
+    /// ```ignore
+    /// let sub = bus.subscribe_many(["game/**"], ["game/*/debug/**"], DeliveryMode::Latest)?;
+    /// ```
+    pub fn subscribe_many(
+        &self,
+        includes: impl IntoIterator<Item = impl TryInto<Topic, Error = TopicError>>,
+        excludes: impl IntoIterator<Item = impl TryInto<Topic, Error = TopicError>>,
+        mode: DeliveryMode,
+    ) -> Result<Subscription, BusError> {
+        let registration = Registration {
+            excludes: distinct_filters(excludes)?,
+            ..Registration::new(mode)
+        };
+        self.register(distinct_filters(includes)?, registration)
+    }
+
+    /// Subscribe to a topic pattern, receiving only envelopes whose JSON
+    /// payload satisfies `predicate`.
+    ///
+    /// The predicate runs at publish time, so the subscriber is never woken
+    /// for envelopes it rejects. Non-JSON payloads never match. Uses
+    /// [`DeliveryMode::Latest`].
+    #[cfg(feature = "serde")]
+    pub fn subscribe_where(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        predicate: impl TryInto<Predicate, Error = PredicateError>,
+    ) -> Result<Subscription, BusError> {
+        let filter = filter.try_into()?;
+        let registration = Registration {
+            predicate: Some(predicate.try_into()?),
+            ..Registration::new(DeliveryMode::Latest)
+        };
+        self.register(vec![filter], registration)
+    }
+
+    /// Join the shared subscription `group` on `filter`, so each matching
+    /// message goes to only one of its members.
+    ///
+    /// The same as subscribing to `$share/<group>/<filter>`, which picks
+    /// members round-robin, but with an explicit strategy. The strategy of
+    /// the group's first member applies while the group exists.
+    ///
+    /// Shared members are not seeded with retained messages. Use
+    /// [`DeliveryMode::Queue`] unless skipping work is acceptable.
+    pub fn subscribe_shared(
+        &self,
+        group: &str,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        mode: DeliveryMode,
+        strategy: ShareStrategy,
+    ) -> Result<Subscription, BusError> {
+        if group.is_empty() || group.contains(['/', '*']) {
+            return Err(TopicError::InvalidShare.into());
+        }
         let filter = filter.try_into()?;
+        let shared = Topic::try_from(format!("{SHARE_PREFIX}/{group}/{filter}"))?;
+        let registration = Registration {
+            strategy,
+            ..Registration::new(mode)
+        };
+        self.register(vec![shared], registration)
+    }
+
+    /// Add subscriber `id` to the shared group `key`, creating it if needed.
+    fn join(&self, key: Arc<str>, id: SubscriberId, strategy: ShareStrategy) -> Arc<ShareGroup> {
+        // Join while holding the map entry, so a last member leaving at the
+        // same time cannot remove the group in between.
+        let group = self
+            .inner
+            .groups
+            .entry(Arc::clone(&key))
+            .or_insert_with(|| Arc::new(ShareGroup::new(key, strategy)));
+        group.join(id);
+        Arc::clone(&group)
+    }
+
+    /// Register a subscription routed by `filters`.
+    ///
+    /// A shared filter must be the only filter, without exclusions.
+    fn register(
+        &self,
+        filters: Vec<Topic>,
+        registration: Registration,
+    ) -> Result<Subscription, BusError> {
+        let share = match filters.as_slice() {
+            [filter] => share::split(filter)?,
+            _ => None,
+        };
+        let shared = share.is_some();
+        if (shared && !registration.excludes.is_empty())
+            || (!shared && filters.iter().any(share::is_shared))
+        {
+            return Err(TopicError::InvalidShare.into());
+        }
+
         let id = SubscriberId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
         let (tx, rx) = watch::channel(None);
+        let mailbox = Arc::new(Mailbox::new(registration.mode));
+        let (filters, group) = match share {
+            Some((key, filter)) => (
+                vec![filter],
+                Some(self.join(key, id, registration.strategy)),
+            ),
+            None => (filters, None),
+        };
+        let subscriber = Subscriber {
+            sender: tx,
+            mailbox: Arc::clone(&mailbox),
+            #[cfg(feature = "serde")]
+            predicate: registration.predicate,
+            group,
+            excludes: registration.excludes.clone(),
+        };
+        let routed = |topic: &Topic| filters.iter().any(|f| topic_matches(f, topic));
 
-        self.inner.subscribers.insert(id, Subscriber { sender: tx });
+        // Seed before the subscriber becomes routable so live publishes
+        // always land after the retained state they supersede. Retained
+        // state is not replayed to shared members, or the group would
+        // process it once per member.
+        let seeded = if shared {
+            Vec::new()
+        } else {
+            self.retained_for(&subscriber, routed)
+        };
+        seeded.iter().for_each(|envelope| {
+            subscriber.offer(envelope);
+        });
+        self.inner.subscribers.insert(id, subscriber);
+        self.inner.routes.update(|trie| {
+            trie.filters_changed(0, filters.len());
+            filters.iter().for_each(|filter| trie.insert(filter, id));
+        });
 
-        self.inner
-            .trie
-            .write()
-            .expect("trie lock poisoned")
-            .insert(&filter, id);
+        // A `publish_retained` racing with registration may have stored its
+        // envelope after the seed read but routed with the old snapshot.
+        // Offer anything newer than what the subscriber has already seen.
+        if !shared && let Some(subscriber) = self.inner.subscribers.get(&id) {
+            self.retained_for(&subscriber, routed)
+                .iter()
+                .filter(|e| {
+                    !seeded
+                        .iter()
+                        .any(|s| s.topic == e.topic && s.timestamp == e.timestamp)
+                })
+                .for_each(|envelope| {
+                    let newer = subscriber
+                        .sender
+                        .borrow()
+                        .as_ref()
+                        .is_none_or(|latest| latest.timestamp < envelope.timestamp);
+                    if newer {
+                        subscriber.offer(envelope);
+                    }
+                });
+        }
 
         Ok(Subscription {
             id,
-            filter,
+            filters,
+            excludes: registration.excludes,
             bus: Arc::clone(&self.inner),
             receiver: rx,
+            mailbox,
+            expiry: None,
         })
     }
 }
 
+/// Per-subscription settings fixed at registration.
+struct Registration {
+    mode: DeliveryMode,
+    #[cfg(feature = "serde")]
+    predicate: Option<Predicate>,
+    /// Only used by the first member of a shared group.
+    strategy: ShareStrategy,
+    excludes: Vec<Topic>,
+}
+
+impl Registration {
+    fn new(mode: DeliveryMode) -> Self {
+        Self {
+            mode,
+            #[cfg(feature = "serde")]
+            predicate: None,
+            strategy: ShareStrategy::default(),
+            excludes: Vec::new(),
+        }
+    }
+}
+
 impl Default for Bus {
     fn default() -> Self {
         Self::new()
@@ -157,42 +688,239 @@ impl Default for Bus {
 /// A subscription handle. Dropping it unsubscribes automatically.
 pub struct Subscription {
     id: SubscriberId,
-    filter: Topic,
+    /// Routed filters, without any `$share/<group>/` prefix.
+    filters: Vec<Topic>,
+    excludes: Vec<Topic>,
     bus: Arc<BusInner>,
     receiver: watch::Receiver<Option<Envelope>>,
+    mailbox: Arc<Mailbox>,
+    expiry: Option<ExpiryWatch>,
 }
 
 impl Subscription {
-    /// Wait for the next value change and return the envelope.
+    /// Wait for the next message and return the envelope.
     ///
-    /// Returns `None` if the bus is dropped.
+    /// In [`DeliveryMode::Latest`] this is the most recent message since the
+    /// last call; in [`DeliveryMode::Queue`] it is the oldest queued one.
     pub async fn recv(&mut self) -> Option<Envelope> {
-        self.receiver.changed().await.ok()?;
-        self.receiver.borrow_and_update().clone()
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
     }
 
-    /// Read the current latest value without waiting.
+    /// Poll for the next message. See [`Subscription::recv`].
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        while let Poll::Ready(envelope) = self.mailbox.poll_recv(cx) {
+            let Some(expiry) = &mut self.expiry else {
+                if envelope.is_expired() {
+                    continue;
+                }
+                return Poll::Ready(Some(envelope));
+            };
+            if !envelope.is_expired() {
+                expiry.observe(&envelope);
+                return Poll::Ready(Some(envelope));
+            }
+            // The topic's shown value is superseded by one that is already
+            // stale, so report the topic as expired right away.
+            if expiry.is_live(&envelope.topic) {
+                expiry.observe(&envelope);
+            }
+        }
+
+        match &mut self.expiry {
+            Some(expiry) => expiry.poll_expired(cx).map(Some),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Also yield each topic's last envelope again once its TTL runs out.
+    ///
+    /// The repeated envelope is unchanged apart from
+    /// [`Envelope::is_expired`] now returning `true`, so a UI can mark the
+    /// topic as stale. A newer envelope for the topic cancels the event.
+    ///
+    /// Expiry timers run on the tokio runtime, so async receivers must poll
+    /// the subscription from within one. [`Subscription::recv_blocking`]
+    /// and [`Subscription::recv_timeout`] wait for expiries without one.
+    pub fn notify_expiry(mut self) -> Self {
+        self.expiry.get_or_insert_with(ExpiryWatch::default);
+        self
+    }
+
+    /// Read the latest value delivered to this subscription without waiting.
+    ///
+    /// Returns `None` once that value has expired.
     pub fn get(&self) -> Option<Envelope> {
-        self.receiver.borrow().clone()
+        self.receiver.borrow().clone().filter(|e| !e.is_expired())
     }
 
     /// Clone the underlying watch receiver for use in async producers.
     pub fn clone_receiver(&self) -> watch::Receiver<Option<Envelope>> {
         self.receiver.clone()
     }
+
+    /// Filters this subscription receives topics for.
+    pub fn filters(&self) -> &[Topic] {
+        &self.filters
+    }
+
+    /// Filters whose topics are skipped even if an include matches.
+    pub fn exclusions(&self) -> &[Topic] {
+        &self.excludes
+    }
+
+    /// Also receive topics matching `filter`.
+    ///
+    /// Retained messages on topics the subscription did not already cover
+    /// are delivered first, as if it had just subscribed to them.
+    pub fn add_filter(
+        &mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<(), BusError> {
+        let filter = filter.try_into()?;
+        self.check_unshared(&filter)?;
+        if self.filters.contains(&filter) {
+            return Ok(());
+        }
+
+        let bus = Bus {
+            inner: Arc::clone(&self.bus),
+        };
+        if let Some(subscriber) = self.bus.subscribers.get(&self.id) {
+            bus.retained_for(&subscriber, |topic| {
+                topic_matches(&filter, topic)
+                    && !self.filters.iter().any(|f| topic_matches(f, topic))
+            })
+            .iter()
+            .for_each(|envelope| {
+                subscriber.offer(envelope);
+            });
+        }
+        self.bus.routes.update(|trie| {
+            trie.filters_changed(self.filters.len(), self.filters.len() + 1);
+            trie.insert(&filter, self.id);
+        });
+        self.filters.push(filter);
+        Ok(())
+    }
+
+    /// Stop receiving topics through `filter`, returning whether it was one
+    /// of this subscription's filters. Messages already queued are kept.
+    pub fn remove_filter(
+        &mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<bool, BusError> {
+        let filter = filter.try_into()?;
+        self.check_unshared(&filter)?;
+        let Some(index) = self.filters.iter().position(|f| *f == filter) else {
+            return Ok(false);
+        };
+        self.filters.remove(index);
+        self.bus.routes.update(|trie| {
+            trie.remove(&filter, self.id);
+            trie.filters_changed(self.filters.len() + 1, self.filters.len());
+        });
+        Ok(true)
+    }
+
+    /// Skip topics matching `filter`, even if one of the filters matches.
+    pub fn add_exclusion(
+        &mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<(), BusError> {
+        let filter = filter.try_into()?;
+        self.check_unshared(&filter)?;
+        if !self.excludes.contains(&filter) {
+            self.excludes.push(filter);
+            self.sync_exclusions();
+        }
+        Ok(())
+    }
+
+    /// Stop skipping topics matching `filter`, returning whether it was
+    /// one of this subscription's exclusions.
+    pub fn remove_exclusion(
+        &mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<bool, BusError> {
+        let filter = filter.try_into()?;
+        let Some(index) = self.excludes.iter().position(|f| *f == filter) else {
+            return Ok(false);
+        };
+        self.excludes.remove(index);
+        self.sync_exclusions();
+        Ok(true)
+    }
+
+    /// Shared subscriptions keep the single filter they were created with,
+    /// and no exclusions.
+    fn check_unshared(&self, filter: &Topic) -> Result<(), BusError> {
+        let shared = self
+            .bus
+            .subscribers
+            .get(&self.id)
+            .is_some_and(|subscriber| subscriber.group.is_some());
+        if shared || share::is_shared(filter) {
+            return Err(TopicError::InvalidShare.into());
+        }
+        Ok(())
+    }
+
+    fn sync_exclusions(&self) {
+        if let Some(mut subscriber) = self.bus.subscribers.get_mut(&self.id) {
+            subscriber.excludes = self.excludes.clone();
+        }
+    }
+}
+
+/// Parse `filters`, dropping repeats. The trie routes a subscriber once per
+/// filter, but removes every route of it at once.
+fn distinct_filters(
+    filters: impl IntoIterator<Item = impl TryInto<Topic, Error = TopicError>>,
+) -> Result<Vec<Topic>, TopicError> {
+    let mut distinct = Vec::new();
+    for filter in filters {
+        let filter = filter.try_into()?;
+        if !distinct.contains(&filter) {
+            distinct.push(filter);
+        }
+    }
+    Ok(distinct)
 }
 
 impl Drop for Subscription {
     fn drop(&mut self) {
-        self.bus.subscribers.remove(&self.id);
-        if let Ok(mut trie) = self.bus.trie.write() {
-            trie.remove(&self.filter, self.id);
+        let removed = self.bus.subscribers.remove(&self.id);
+        self.bus.routes.update(|trie| {
+            self.filters
+                .iter()
+                .for_each(|filter| trie.remove(filter, self.id));
+            trie.filters_changed(self.filters.len(), 0);
+        });
+
+        // Hand whatever this member had not received yet to the rest of
+        // its group.
+        if let Some((
+            _,
+            Subscriber {
+                group: Some(group), ..
+            },
+        )) = removed
+        {
+            if group.leave(self.id) {
+                self.bus.groups.remove_if(group.key(), |_, g| g.is_empty());
+            } else {
+                self.mailbox.drain().iter().for_each(|envelope| {
+                    self.bus.offer_shared(&group, envelope);
+                });
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     #[tokio::test]
@@ -226,7 +954,275 @@ mod tests {
         bus.publish("counter", "3").unwrap();
 
         let envelope = sub.recv().await.unwrap();
-        assert!(envelope.payload.contains('3'));
+        assert!(envelope.payload.as_str().unwrap().contains('3'));
+    }
+
+    #[tokio::test]
+    async fn queue_keeps_every_message() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe_with("chat", DeliveryMode::queue(8)).unwrap();
+
+        bus.publish("chat", "1").unwrap();
+        bus.publish("chat", "2").unwrap();
+        bus.publish("chat", "3").unwrap();
+
+        for expected in ["1", "2", "3"] {
+            assert_eq!(sub.recv().await.unwrap().payload, expected);
+        }
+        assert_eq!(sub.get().unwrap().payload, "3");
+    }
+
+    #[tokio::test]
+    async fn queue_drop_oldest() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with(
+                "feed",
+                DeliveryMode::Queue {
+                    capacity: 2,
+                    overflow: Overflow::DropOldest,
+                },
+            )
+            .unwrap();
+
+        ["1", "2", "3"].into_iter().for_each(|p| {
+            assert_eq!(bus.publish("feed", p).unwrap(), 1);
+        });
+
+        assert_eq!(sub.recv().await.unwrap().payload, "2");
+        assert_eq!(sub.recv().await.unwrap().payload, "3");
+    }
+
+    #[tokio::test]
+    async fn queue_drop_newest() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with(
+                "feed",
+                DeliveryMode::Queue {
+                    capacity: 2,
+                    overflow: Overflow::DropNewest,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(bus.publish("feed", "1").unwrap(), 1);
+        assert_eq!(bus.publish("feed", "2").unwrap(), 1);
+        assert_eq!(bus.publish("feed", "3").unwrap(), 0);
+
+        assert_eq!(sub.recv().await.unwrap().payload, "1");
+        assert_eq!(sub.recv().await.unwrap().payload, "2");
+    }
+
+    #[tokio::test]
+    async fn queue_overflow_error_reaches_publisher() {
+        let bus = Bus::new();
+        let _strict = bus
+            .subscribe_with(
+                "feed",
+                DeliveryMode::Queue {
+                    capacity: 1,
+                    overflow: Overflow::Error,
+                },
+            )
+            .unwrap();
+        let mut latest = bus.subscribe("feed").unwrap();
+
+        assert_eq!(bus.publish("feed", "1").unwrap(), 2);
+        assert!(matches!(
+            bus.publish("feed", "2"),
+            Err(BusError::QueueFull(1))
+        ));
+
+        assert_eq!(latest.recv().await.unwrap().payload, "2");
+    }
+
+    #[tokio::test]
+    async fn retained_seeds_late_subscriber() {
+        let bus = Bus::new();
+        bus.publish_retained("game/valorant/status", "online")
+            .unwrap();
+
+        let mut sub = bus.subscribe("game/*/status").unwrap();
+        assert_eq!(sub.get().unwrap().payload, "online");
+        assert_eq!(sub.recv().await.unwrap().payload, "online");
+    }
+
+    #[tokio::test]
+    async fn retained_seeds_queue_with_every_match() {
+        let bus = Bus::new();
+        bus.publish_retained("game/valorant/status", "a").unwrap();
+        bus.publish_retained("game/apex/status", "b").unwrap();
+        bus.publish_retained("game/apex/status", "c").unwrap();
+        bus.publish_retained("other", "x").unwrap();
+
+        let mut sub = bus
+            .subscribe_with("game/**", DeliveryMode::queue(8))
+            .unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "a");
+        assert_eq!(sub.recv().await.unwrap().payload, "c");
+    }
+
+    #[tokio::test]
+    async fn retained_query_and_clear() {
+        let bus = Bus::new();
+        bus.publish_retained("game/valorant/status", "online")
+            .unwrap();
+        bus.publish("game/apex/status", "not retained").unwrap();
+
+        let retained = bus.retained("game/**").unwrap();
+        assert_eq!(retained.len(), 1);
+        assert_eq!(&*retained[0].topic, "game/valorant/status");
+
+        assert!(
+            bus.clear_retained("game/valorant/status")
+                .unwrap()
+                .is_some()
+        );
+        assert!(bus.retained("**").unwrap().is_empty());
+        assert!(bus.subscribe("game/**").unwrap().get().is_none());
+    }
+
+    #[tokio::test]
+    async fn binary_payload_roundtrip() {
+        let bus = Bus::new();
+        let mut sub1 = bus.subscribe("frame").unwrap();
+        let mut sub2 = bus.subscribe("frame").unwrap();
+
+        assert_eq!(bus.publish_bytes("frame", vec![0u8, 159, 146]).unwrap(), 2);
+
+        let a = sub1.recv().await.unwrap();
+        let b = sub2.recv().await.unwrap();
+        assert_eq!(a.payload.as_bytes(), &[0, 159, 146]);
+        assert!(a.payload.as_str().is_none());
+        let (Payload::Bytes(a), Payload::Bytes(b)) = (a.payload, b.payload) else {
+            panic!("expected binary payloads");
+        };
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+
+    #[tokio::test]
+    async fn sequence_per_topic() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("game/*/kill").unwrap();
+
+        bus.publish("game/apex/kill", "1").unwrap();
+        bus.publish("game/valorant/kill", "1").unwrap();
+        assert_eq!(sub.recv().await.unwrap().sequence, 1);
+
+        bus.publish("game/apex/kill", "2").unwrap();
+        bus.publish("game/apex/kill", "3").unwrap();
+        let envelope = sub.recv().await.unwrap();
+        assert_eq!(envelope.sequence, 3);
+        assert!(envelope.published_at <= std::time::SystemTime::now());
+    }
+
+    #[tokio::test]
+    async fn publish_with_metadata() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("img").unwrap();
+
+        let options = PublishOptions::new()
+            .publisher("capture")
+            .header("content-type", "image/png")
+            .retain();
+        bus.publish_with("img", vec![0u8, 1], options).unwrap();
+
+        let envelope = sub.recv().await.unwrap();
+        assert_eq!(envelope.publisher.as_deref(), Some("capture"));
+        assert_eq!(envelope.header("content-type"), Some("image/png"));
+        assert_eq!(envelope.header("missing"), None);
+        assert_eq!(bus.retained("img").unwrap()[0].sequence, 1);
+
+        bus.publish("img", "plain").unwrap();
+        let envelope = sub.recv().await.unwrap();
+        assert!(envelope.publisher.is_none());
+        assert!(envelope.headers.is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_envelopes_are_hidden() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe_with("match", DeliveryMode::queue(8)).unwrap();
+        let ttl = PublishOptions::new()
+            .ttl(Duration::from_millis(20))
+            .retain();
+
+        bus.publish_with("match", "stale", ttl).unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(sub.get().is_none());
+        assert!(bus.retained("match").unwrap().is_empty());
+        assert!(bus.subscribe("match").unwrap().get().is_none());
+
+        bus.publish("match", "fresh").unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "fresh");
+    }
+
+    #[tokio::test]
+    async fn notify_expiry() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("game/*/match").unwrap().notify_expiry();
+        let ttl = PublishOptions::new().ttl(Duration::from_millis(20));
+
+        bus.publish_with("game/apex/match", "running", ttl.clone())
+            .unwrap();
+        let live = sub.recv().await.unwrap();
+        assert!(!live.is_expired());
+
+        let expired = tokio::time::timeout(Duration::from_secs(1), sub.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(expired.is_expired());
+        assert_eq!(expired.sequence, live.sequence);
+
+        // A newer envelope without a TTL cancels the pending event.
+        bus.publish_with("game/apex/match", "running", ttl).unwrap();
+        sub.recv().await.unwrap();
+        bus.publish("game/apex/match", "ended").unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "ended");
+        let pending = tokio::time::timeout(Duration::from_millis(60), sub.recv()).await;
+        assert!(pending.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn subscribe_where_filters_payloads() {
+        let bus = Bus::new();
+        let mut mine = bus
+            .subscribe_where("game/*/kill", r#"/killer == "me" && /damage >= 50"#)
+            .unwrap();
+        let mut all = bus
+            .subscribe_with("game/*/kill", DeliveryMode::queue(8))
+            .unwrap();
+
+        let kill =
+            |killer: &str, damage: u32| format!(r#"{{"killer":"{killer}","damage":{damage}}}"#);
+        assert_eq!(bus.publish("game/apex/kill", kill("you", 90)).unwrap(), 1);
+        assert_eq!(bus.publish("game/apex/kill", kill("me", 10)).unwrap(), 1);
+        assert_eq!(bus.publish("game/apex/kill", "not json").unwrap(), 1);
+        assert_eq!(bus.publish("game/apex/kill", kill("me", 80)).unwrap(), 2);
+
+        let envelope = mine.recv().await.unwrap();
+        assert_eq!(envelope.sequence, 4);
+        assert_eq!(all.recv().await.unwrap().sequence, 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn subscribe_where_filters_retained_seed() {
+        let bus = Bus::new();
+        bus.publish_retained("match/a", r#"{"live":true}"#).unwrap();
+        bus.publish_retained("match/b", r#"{"live":false}"#)
+            .unwrap();
+
+        let sub = bus.subscribe_where("match/*", "/live").unwrap();
+        assert_eq!(&*sub.get().unwrap().topic, "match/a");
+
+        assert!(matches!(
+            bus.subscribe_where("match/*", "live"),
+            Err(BusError::Predicate(_))
+        ));
     }
 
     #[tokio::test]
@@ -287,6 +1283,172 @@ mod tests {
         assert!(bus.subscribe("").is_err());
     }
 
+    #[tokio::test]
+    async fn shared_subscription_delivers_once() {
+        let bus = Bus::new();
+        let mut plain = bus
+            .subscribe_with("jobs/**", DeliveryMode::queue(8))
+            .unwrap();
+        let mut a = bus
+            .subscribe_with("$share/workers/jobs/**", DeliveryMode::queue(8))
+            .unwrap();
+        let mut b = bus
+            .subscribe_with("$share/workers/jobs/**", DeliveryMode::queue(8))
+            .unwrap();
+
+        for job in ["1", "2", "3", "4"] {
+            // The plain subscriber plus one worker.
+            assert_eq!(bus.publish("jobs/render", job).unwrap(), 2);
+        }
+        for job in ["1", "2", "3", "4"] {
+            assert_eq!(plain.recv().await.unwrap().payload, job);
+        }
+        assert_eq!(a.recv().await.unwrap().payload, "1");
+        assert_eq!(b.recv().await.unwrap().payload, "2");
+        assert_eq!(a.recv().await.unwrap().payload, "3");
+        assert_eq!(b.recv().await.unwrap().payload, "4");
+        assert_eq!(bus.topics()[0].subscribers, 3);
+    }
+
+    #[tokio::test]
+    async fn dropped_member_work_is_rebalanced() {
+        let bus = Bus::new();
+        bus.publish_retained("jobs/old", "stale").unwrap();
+        let subscribe = || {
+            bus.subscribe_shared(
+                "workers",
+                "jobs/*",
+                DeliveryMode::queue(8),
+                ShareStrategy::LeastRecentlyDelivered,
+            )
+            .unwrap()
+        };
+        let mut a = subscribe();
+        let b = subscribe();
+        // Shared members are not seeded with retained state.
+        assert!(a.mailbox.drain().is_empty());
+
+        for job in ["1", "2", "3", "4"] {
+            bus.publish("jobs/render", job).unwrap();
+        }
+        drop(b);
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(a.recv().await.unwrap().payload);
+        }
+        assert_eq!(received, ["1", "3", "2", "4"]);
+
+        drop(a);
+        assert!(bus.inner.groups.is_empty());
+        assert_eq!(bus.publish("jobs/render", "5").unwrap(), 0);
+    }
+
+    #[test]
+    fn invalid_shared_filters() {
+        let bus = Bus::new();
+        assert!(bus.subscribe("$share/workers").is_err());
+        let err = bus
+            .subscribe_shared(
+                "a/b",
+                "jobs",
+                DeliveryMode::Latest,
+                ShareStrategy::RoundRobin,
+            )
+            .err()
+            .unwrap();
+        assert!(matches!(err, BusError::Topic(TopicError::InvalidShare)));
+    }
+
+    #[tokio::test]
+    async fn multi_filter_with_exclusions() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_many(
+                ["game/**", "game/*/status"],
+                ["game/*/debug/**"],
+                DeliveryMode::queue(8),
+            )
+            .unwrap();
+
+        // Matched by both includes, delivered once.
+        assert_eq!(bus.publish("game/apex/status", "online").unwrap(), 1);
+        assert_eq!(bus.publish("game/apex/debug/fps", "144").unwrap(), 0);
+        assert_eq!(bus.publish("chat/message", "hi").unwrap(), 0);
+        bus.publish("game/apex/score", "12").unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "online");
+        assert_eq!(sub.recv().await.unwrap().payload, "12");
+
+        sub.remove_exclusion("game/*/debug/**").unwrap();
+        sub.add_exclusion("game/*/score").unwrap();
+        bus.publish("game/apex/score", "13").unwrap();
+        bus.publish("game/apex/debug/fps", "60").unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "60");
+    }
+
+    #[tokio::test]
+    async fn filters_change_at_runtime() {
+        let bus = Bus::new();
+        bus.publish_retained("chat/topic", "welcome").unwrap();
+        bus.publish_retained("game/apex/status", "online").unwrap();
+        let mut sub = bus
+            .subscribe_with("game/**", DeliveryMode::queue(8))
+            .unwrap();
+        assert_eq!(sub.recv().await.unwrap().payload, "online");
+
+        // Only retained state the subscription did not cover is replayed.
+        sub.add_filter("**").unwrap();
+        sub.add_filter("**").unwrap();
+        assert_eq!(sub.filters().len(), 2);
+        assert_eq!(sub.recv().await.unwrap().payload, "welcome");
+        assert_eq!(bus.publish("game/apex/score", "1").unwrap(), 1);
+        assert_eq!(sub.recv().await.unwrap().payload, "1");
+
+        assert!(sub.remove_filter("game/**").unwrap());
+        assert!(!sub.remove_filter("game/**").unwrap());
+        assert!(sub.remove_filter("**").unwrap());
+        assert!(sub.filters().is_empty());
+
+        let mut sub = bus
+            .subscribe_many(["chat/*", "chat/*"], [""; 0], DeliveryMode::Latest)
+            .unwrap();
+        assert_eq!(sub.filters().len(), 1);
+        assert!(sub.remove_filter("chat/*").unwrap());
+        assert!(sub.filters().is_empty());
+        assert_eq!(bus.publish("chat/message", "hi").unwrap(), 0);
+        assert!(bus.topics().iter().all(|t| t.subscribers == 0));
+    }
+
+    #[test]
+    fn shared_subscriptions_have_one_filter() {
+        let bus = Bus::new();
+        let err = bus
+            .subscribe_many(
+                ["jobs/**", "$share/workers/tasks/**"],
+                [""; 0],
+                DeliveryMode::Latest,
+            )
+            .err()
+            .unwrap();
+        assert!(matches!(err, BusError::Topic(TopicError::InvalidShare)));
+        let err = bus
+            .subscribe_many(
+                ["$share/workers/jobs/**"],
+                ["jobs/debug"],
+                DeliveryMode::Latest,
+            )
+            .err()
+            .unwrap();
+        assert!(matches!(err, BusError::Topic(TopicError::InvalidShare)));
+
+        let mut shared = bus.subscribe("$share/workers/jobs/**").unwrap();
+        assert!(shared.add_filter("tasks/**").is_err());
+        assert!(shared.remove_filter("jobs/**").is_err());
+        assert_eq!(shared.filters().len(), 1);
+        assert!(shared.add_exclusion("jobs/debug").is_err());
+        let mut plain = bus.subscribe("jobs/**").unwrap();
+        assert!(plain.add_filter("$share/workers/tasks/**").is_err());
+    }
+
     #[cfg(feature = "serde")]
     #[tokio::test]
     async fn serde_struct_roundtrip() {
@@ -0,0 +1,616 @@
+//! Bridge between the bus and an external MQTT broker.
+//!
+//! Bus topics map to MQTT topics under a prefix: with the prefix `recon`,
+//! `game/apex/status` becomes `recon/game/apex/status` and the filter
+//! `game/*/status` becomes `recon/game/+/status`. `**` maps to `#`.
+//!
+//! Outbound routes mirror bus topics to the broker; inbound routes
+//! subscribe on the broker and republish into the bus. Messages the bridge
+//! republished itself are never mirrored back out. MQTT 3.1.1 does not let
+//! a client opt out of its own messages, so a topic both mirrored and
+//! subscribed reaches the bus twice there; with MQTT 5 it does not.
+//!
+//! The broker side is abstracted as [`MqttClient`] and [`MqttEvents`],
+//! implemented for both `rumqttc` protocol versions, so the bridge can also
+//! run against an in-process stub.
+
+use std::{fmt, future::Future, time::Duration};
+
+use rumqttc::v5::mqttbytes::v5::{Filter, Packet as PacketV5};
+use tokio::task::JoinHandle;
+
+use crate::{
+    Bus, DeliveryMode, Envelope, Payload, PublishOptions, Topic, TopicError, topic_matches,
+};
+
+/// Principal id stamped as the publisher of inbound messages.
+const PUBLISHER: &str = "mqtt";
+
+/// How many envelopes each outbound route buffers while the broker is slow.
+const OUTBOUND_QUEUE: usize = 1024;
+
+/// Capacity of the request channel between a `rumqttc` client and its event
+/// loop.
+const REQUEST_CAPACITY: usize = 64;
+
+/// How long to wait before reconnecting after a connection error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// MQTT delivery guarantee.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Qos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl From<Qos> for rumqttc::QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => Self::AtMostOnce,
+            Qos::AtLeastOnce => Self::AtLeastOnce,
+            Qos::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+
+impl From<rumqttc::QoS> for Qos {
+    fn from(qos: rumqttc::QoS) -> Self {
+        match qos {
+            rumqttc::QoS::AtMostOnce => Self::AtMostOnce,
+            rumqttc::QoS::AtLeastOnce => Self::AtLeastOnce,
+            rumqttc::QoS::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+
+impl From<Qos> for rumqttc::v5::mqttbytes::QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => Self::AtMostOnce,
+            Qos::AtLeastOnce => Self::AtLeastOnce,
+            Qos::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+
+impl From<rumqttc::v5::mqttbytes::QoS> for Qos {
+    fn from(qos: rumqttc::v5::mqttbytes::QoS) -> Self {
+        match qos {
+            rumqttc::v5::mqttbytes::QoS::AtMostOnce => Self::AtMostOnce,
+            rumqttc::v5::mqttbytes::QoS::AtLeastOnce => Self::AtLeastOnce,
+            rumqttc::v5::mqttbytes::QoS::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+
+/// A message on the broker side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: Qos,
+    pub retain: bool,
+}
+
+/// What the broker connection reports to the bridge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttEvent {
+    /// A (re)connection was acknowledged. Inbound routes subscribe again.
+    Connected,
+    Message(MqttMessage),
+}
+
+/// The broker rejected a request or the connection is gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttError(pub String);
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mqtt: {}", self.0)
+    }
+}
+
+impl std::error::Error for MqttError {}
+
+/// Sending half of a broker connection.
+pub trait MqttClient: Clone + Send + Sync + 'static {
+    fn publish(&self, message: MqttMessage) -> impl Future<Output = Result<(), MqttError>> + Send;
+
+    fn subscribe(
+        &self,
+        filter: String,
+        qos: Qos,
+    ) -> impl Future<Output = Result<(), MqttError>> + Send;
+}
+
+/// Receiving half of a broker connection. Drives the connection, so it
+/// must be polled continuously.
+pub trait MqttEvents: Send {
+    /// The next event, or `None` once the connection is closed for good.
+    fn next(&mut self) -> impl Future<Output = Option<MqttEvent>> + Send;
+}
+
+/// The MQTT topic a concrete bus topic is mirrored to, or `None` if it
+/// holds characters MQTT reserves for wildcards.
+pub fn to_mqtt_topic(prefix: &str, topic: &str) -> Option<String> {
+    if topic.contains(['*', '+', '#']) {
+        return None;
+    }
+    Some(join(prefix, topic))
+}
+
+/// The MQTT filter a bus filter subscribes with: `*` becomes `+` and a
+/// trailing `**` becomes `#`. `None` if the filter has no MQTT equivalent:
+/// a segment glob, a `**` before the end, or a segment holding `+` or `#`.
+pub fn to_mqtt_filter(prefix: &str, filter: &Topic) -> Option<String> {
+    let last = filter.segment_count() - 1;
+    let segments: Option<Vec<&str>> = filter
+        .segments()
+        .enumerate()
+        .map(|(i, segment)| match segment {
+            "*" => Some("+"),
+            "**" if i == last => Some("#"),
+            s if s.contains(['*', '+', '#']) => None,
+            s => Some(s),
+        })
+        .collect();
+    Some(join(prefix, &segments?.join("/")))
+}
+
+/// The bus topic an MQTT topic under `prefix` is republished to, or `None`
+/// if it is outside the prefix, not a valid concrete bus topic, or in the
+/// host's reserved `$sys` namespace.
+pub fn from_mqtt_topic(prefix: &str, topic: &str) -> Option<Topic> {
+    let topic = if prefix.is_empty() {
+        topic
+    } else {
+        topic.strip_prefix(prefix)?.strip_prefix('/')?
+    };
+    Topic::parse_external(topic)
+        .ok()
+        .filter(|topic| !topic.has_wildcards())
+}
+
+fn join(prefix: &str, rest: &str) -> String {
+    if prefix.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{prefix}/{rest}")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    filter: Topic,
+    qos: Qos,
+    retain: bool,
+}
+
+/// Mirrors bus topics to an MQTT broker and back.
+///
+/// ```ignore
+/// let options = rumqttc::MqttOptions::new("recon", "localhost", 1883);
+/// MqttBridge::new(&bus, "recon")
+///     .mirror("game/**", Qos::AtLeastOnce, true)?
+///     .subscribe("home/lights/*", Qos::AtMostOnce)?
+///     .run_v311(options)
+///     .await;
+/// ```
+#[derive(Clone)]
+pub struct MqttBridge {
+    bus: Bus,
+    prefix: String,
+    outbound: Vec<Route>,
+    inbound: Vec<Route>,
+}
+
+impl MqttBridge {
+    /// A bridge mapping bus topics under `prefix`. An empty prefix maps
+    /// topics unchanged.
+    pub fn new(bus: &Bus, prefix: impl Into<String>) -> Self {
+        Self {
+            bus: bus.clone(),
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+            outbound: Vec::new(),
+            inbound: Vec::new(),
+        }
+    }
+
+    /// Publish bus messages matching `filter` to the broker with `qos`,
+    /// setting the MQTT retained flag if `retain` is set.
+    pub fn mirror(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        qos: Qos,
+        retain: bool,
+    ) -> Result<Self, TopicError> {
+        self.outbound.push(Route {
+            filter: filter.try_into()?,
+            qos,
+            retain,
+        });
+        Ok(self)
+    }
+
+    /// Subscribe on the broker to the MQTT equivalent of `filter` with
+    /// `qos` and republish what arrives. Messages the broker marks as
+    /// retained become retained on the bus.
+    ///
+    /// Fails with [`TopicError::NoMqttEquivalent`] if `filter` has no MQTT
+    /// form, see [`to_mqtt_filter`].
+    pub fn subscribe(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        qos: Qos,
+    ) -> Result<Self, TopicError> {
+        let filter = filter.try_into()?;
+        if to_mqtt_filter(&self.prefix, &filter).is_none() {
+            return Err(TopicError::NoMqttEquivalent);
+        }
+        self.inbound.push(Route {
+            filter,
+            qos,
+            retain: false,
+        });
+        Ok(self)
+    }
+
+    /// Connect with MQTT 3.1.1 and bridge until the task is dropped.
+    pub async fn run_v311(self, options: rumqttc::MqttOptions) {
+        let (client, events) = rumqttc::AsyncClient::new(options, REQUEST_CAPACITY);
+        self.run(client, events).await;
+    }
+
+    /// Connect with MQTT 5 and bridge until the task is dropped.
+    pub async fn run_v5(self, options: rumqttc::v5::MqttOptions) {
+        let (client, events) = rumqttc::v5::AsyncClient::new(options, REQUEST_CAPACITY);
+        self.run(client, events).await;
+    }
+
+    /// Bridge over any broker connection until `events` ends.
+    pub async fn run<C: MqttClient, E: MqttEvents>(self, client: C, mut events: E) {
+        let _outbound = Tasks(
+            self.outbound
+                .iter()
+                .filter_map(|route| self.forward(route, client.clone()))
+                .collect(),
+        );
+
+        while let Some(event) = events.next().await {
+            match event {
+                MqttEvent::Connected => {
+                    // Subscribing waits on the connection this loop drives.
+                    for route in &self.inbound {
+                        // `subscribe` only accepts filters with an MQTT form.
+                        let Some(filter) = to_mqtt_filter(&self.prefix, &route.filter) else {
+                            continue;
+                        };
+                        let client = client.clone();
+                        let qos = route.qos;
+                        tokio::spawn(async move { client.subscribe(filter, qos).await });
+                    }
+                }
+                MqttEvent::Message(message) => self.receive(message),
+            }
+        }
+    }
+
+    /// Mirror `route` to the broker from a spawned task.
+    fn forward<C: MqttClient>(&self, route: &Route, client: C) -> Option<JoinHandle<()>> {
+        let mut sub = self
+            .bus
+            .subscribe_with(&route.filter, DeliveryMode::queue(OUTBOUND_QUEUE))
+            .ok()?;
+        let prefix = self.prefix.clone();
+        let route = route.clone();
+        Some(tokio::spawn(async move {
+            while let Some(envelope) = sub.recv().await {
+                if let Some(message) = outbound_message(&prefix, &route, &envelope) {
+                    let _ = client.publish(message).await;
+                }
+            }
+        }))
+    }
+
+    /// Republish a broker message into the bus if an inbound route
+    /// matches it.
+    fn receive(&self, message: MqttMessage) {
+        let Some(topic) = from_mqtt_topic(&self.prefix, &message.topic) else {
+            return;
+        };
+        if !self
+            .inbound
+            .iter()
+            .any(|route| topic_matches(&route.filter, &topic))
+        {
+            return;
+        }
+        let mut options = PublishOptions::new().publisher(PUBLISHER);
+        options.retain = message.retain;
+        let payload = match String::from_utf8(message.payload) {
+            Ok(text) => Payload::from(text),
+            Err(e) => Payload::from(e.into_bytes()),
+        };
+        let _ = self.bus.publish_with(&topic, payload, options);
+    }
+}
+
+fn outbound_message(prefix: &str, route: &Route, envelope: &Envelope) -> Option<MqttMessage> {
+    if envelope.publisher.as_deref() == Some(PUBLISHER) {
+        return None;
+    }
+    Some(MqttMessage {
+        topic: to_mqtt_topic(prefix, &envelope.topic)?,
+        payload: envelope.payload.as_bytes().to_vec(),
+        qos: route.qos,
+        retain: route.retain,
+    })
+}
+
+/// Aborts the outbound forwarders when the bridge stops.
+struct Tasks(Vec<JoinHandle<()>>);
+
+impl Drop for Tasks {
+    fn drop(&mut self) {
+        self.0.iter().for_each(JoinHandle::abort);
+    }
+}
+
+impl MqttClient for rumqttc::AsyncClient {
+    async fn publish(&self, message: MqttMessage) -> Result<(), MqttError> {
+        rumqttc::AsyncClient::publish(
+            self,
+            message.topic,
+            message.qos.into(),
+            message.retain,
+            message.payload,
+        )
+        .await
+        .map_err(|e| MqttError(e.to_string()))
+    }
+
+    async fn subscribe(&self, filter: String, qos: Qos) -> Result<(), MqttError> {
+        rumqttc::AsyncClient::subscribe(self, filter, qos.into())
+            .await
+            .map_err(|e| MqttError(e.to_string()))
+    }
+}
+
+impl MqttEvents for rumqttc::EventLoop {
+    async fn next(&mut self) -> Option<MqttEvent> {
+        use rumqttc::{Event, Packet};
+
+        loop {
+            match self.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => return Some(MqttEvent::Connected),
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    return Some(MqttEvent::Message(MqttMessage {
+                        topic: publish.topic,
+                        payload: publish.payload.to_vec(),
+                        qos: publish.qos.into(),
+                        retain: publish.retain,
+                    }));
+                }
+                Ok(_) => {}
+                // Polling again reconnects.
+                Err(_) => tokio::time::sleep(RECONNECT_DELAY).await,
+            }
+        }
+    }
+}
+
+impl MqttClient for rumqttc::v5::AsyncClient {
+    async fn publish(&self, message: MqttMessage) -> Result<(), MqttError> {
+        rumqttc::v5::AsyncClient::publish(
+            self,
+            message.topic,
+            message.qos.into(),
+            message.retain,
+            message.payload,
+        )
+        .await
+        .map_err(|e| MqttError(e.to_string()))
+    }
+
+    /// Subscribes with "no local" so the bridge's own messages do not come
+    /// back.
+    async fn subscribe(&self, filter: String, qos: Qos) -> Result<(), MqttError> {
+        let mut filter = Filter::new(filter, qos.into());
+        filter.nolocal = true;
+        self.subscribe_many([filter])
+            .await
+            .map_err(|e| MqttError(e.to_string()))
+    }
+}
+
+impl MqttEvents for rumqttc::v5::EventLoop {
+    async fn next(&mut self) -> Option<MqttEvent> {
+        use rumqttc::v5::Event;
+
+        loop {
+            match self.poll().await {
+                Ok(Event::Incoming(PacketV5::ConnAck(_))) => return Some(MqttEvent::Connected),
+                Ok(Event::Incoming(PacketV5::Publish(publish))) => {
+                    let Ok(topic) = String::from_utf8(publish.topic.to_vec()) else {
+                        continue;
+                    };
+                    return Some(MqttEvent::Message(MqttMessage {
+                        topic,
+                        payload: publish.payload.to_vec(),
+                        qos: publish.qos.into(),
+                        retain: publish.retain,
+                    }));
+                }
+                Ok(_) => {}
+                Err(_) => tokio::time::sleep(RECONNECT_DELAY).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn topic(s: &str) -> Topic {
+        Topic::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn topic_mapping() {
+        assert_eq!(
+            to_mqtt_topic("recon", "game/apex/status").as_deref(),
+            Some("recon/game/apex/status")
+        );
+        assert_eq!(
+            to_mqtt_topic("", "game/apex/status").as_deref(),
+            Some("game/apex/status")
+        );
+        assert_eq!(to_mqtt_topic("recon", "score/#1"), None);
+        assert_eq!(to_mqtt_topic("recon", "game/*"), None);
+
+        assert_eq!(
+            to_mqtt_filter("recon", &topic("game/*/status")).as_deref(),
+            Some("recon/game/+/status")
+        );
+        assert_eq!(
+            to_mqtt_filter("recon", &topic("**")).as_deref(),
+            Some("recon/#")
+        );
+        assert_eq!(to_mqtt_filter("recon", &topic("a/b+c")), None);
+        assert_eq!(to_mqtt_filter("recon", &topic("a/**/c")), None);
+        assert_eq!(to_mqtt_filter("recon", &topic("a/player-*")), None);
+
+        assert_eq!(
+            from_mqtt_topic("recon", "recon/home/lights/desk"),
+            Some(topic("home/lights/desk"))
+        );
+        assert_eq!(from_mqtt_topic("recon", "reconx/home"), None);
+        assert_eq!(from_mqtt_topic("recon", "recon/home/*"), None);
+        assert_eq!(from_mqtt_topic("recon", "recon/$sys/window/opened"), None);
+        assert_eq!(from_mqtt_topic("", "home"), Some(topic("home")));
+    }
+
+    #[test]
+    fn inbound_filters_need_an_mqtt_form() {
+        let bridge = || MqttBridge::new(&Bus::new(), "recon");
+        for filter in ["game/**/health", "players/player-*"] {
+            assert_eq!(
+                bridge().subscribe(filter, Qos::AtMostOnce).err(),
+                Some(TopicError::NoMqttEquivalent)
+            );
+        }
+        assert!(bridge().subscribe("game/*/health", Qos::AtMostOnce).is_ok());
+    }
+
+    #[test]
+    fn qos_roundtrip() {
+        for qos in [Qos::AtMostOnce, Qos::AtLeastOnce, Qos::ExactlyOnce] {
+            assert_eq!(Qos::from(rumqttc::QoS::from(qos)), qos);
+            assert_eq!(Qos::from(rumqttc::v5::mqttbytes::QoS::from(qos)), qos);
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Sent {
+        Publish(MqttMessage),
+        Subscribe(String, Qos),
+    }
+
+    /// An in-process broker connection that records what the bridge sends.
+    #[derive(Clone)]
+    struct StubClient(mpsc::UnboundedSender<Sent>);
+
+    impl MqttClient for StubClient {
+        async fn publish(&self, message: MqttMessage) -> Result<(), MqttError> {
+            let _ = self.0.send(Sent::Publish(message));
+            Ok(())
+        }
+
+        async fn subscribe(&self, filter: String, qos: Qos) -> Result<(), MqttError> {
+            let _ = self.0.send(Sent::Subscribe(filter, qos));
+            Ok(())
+        }
+    }
+
+    struct StubEvents(mpsc::UnboundedReceiver<MqttEvent>);
+
+    impl MqttEvents for StubEvents {
+        async fn next(&mut self) -> Option<MqttEvent> {
+            self.0.recv().await
+        }
+    }
+
+    #[tokio::test]
+    async fn bridges_both_directions() {
+        let bus = Bus::new();
+        bus.publish_retained("game/apex/status", "online").unwrap();
+        let mut lights = bus.subscribe("home/**").unwrap();
+
+        let (sent_tx, mut sent) = mpsc::unbounded_channel();
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let bridge = MqttBridge::new(&bus, "recon/")
+            .mirror("game/**", Qos::AtLeastOnce, true)
+            .unwrap()
+            .mirror("home/**", Qos::AtMostOnce, false)
+            .unwrap()
+            .subscribe("home/lights/*", Qos::ExactlyOnce)
+            .unwrap();
+        let bridge = tokio::spawn(bridge.run(StubClient(sent_tx), StubEvents(events_rx)));
+
+        // Retained state is mirrored as soon as the bridge starts.
+        assert_eq!(
+            sent.recv().await.unwrap(),
+            Sent::Publish(MqttMessage {
+                topic: "recon/game/apex/status".into(),
+                payload: b"online".to_vec(),
+                qos: Qos::AtLeastOnce,
+                retain: true,
+            })
+        );
+
+        events.send(MqttEvent::Connected).unwrap();
+        assert_eq!(
+            sent.recv().await.unwrap(),
+            Sent::Subscribe("recon/home/lights/+".into(), Qos::ExactlyOnce)
+        );
+
+        events
+            .send(MqttEvent::Message(MqttMessage {
+                topic: "recon/home/lights/desk".into(),
+                payload: b"on".to_vec(),
+                qos: Qos::ExactlyOnce,
+                retain: true,
+            }))
+            .unwrap();
+        let envelope = lights.recv().await.unwrap();
+        assert_eq!(envelope.payload, "on");
+        assert_eq!(envelope.publisher.as_deref(), Some(PUBLISHER));
+        assert_eq!(bus.retained("home/lights/desk").unwrap().len(), 1);
+
+        // Topics outside the inbound routes are ignored, and the inbound
+        // message above was not mirrored back out.
+        events
+            .send(MqttEvent::Message(MqttMessage {
+                topic: "recon/home/doors/front".into(),
+                payload: b"open".to_vec(),
+                qos: Qos::AtMostOnce,
+                retain: false,
+            }))
+            .unwrap();
+        bus.publish("game/apex/status", "offline").unwrap();
+        let Sent::Publish(message) = sent.recv().await.unwrap() else {
+            panic!("expected a publish");
+        };
+        assert_eq!(message.payload, b"offline");
+        assert!(bus.retained("home/doors/**").unwrap().is_empty());
+
+        drop(events);
+        bridge.await.unwrap();
+        assert_eq!(bus.stats().subscribers, 1);
+    }
+}
@@ -0,0 +1,523 @@
+//! Rate-limiting and filtering operators for streams of envelopes.
+//!
+//! Everything here works on any `Stream<Item = Envelope>`, starting with a
+//! [`Subscription`](crate::Subscription), so operators chain:
+//!
+//! ```ignore
+//! let health = bus.subscribe("game/*/health")?
+//!     .distinct()
+//!     .throttle(Duration::from_millis(100));
+//! ```
+//!
+//! [`throttle`](SubscriptionExt::throttle), [`debounce`](SubscriptionExt::debounce)
+//! and [`sample`](SubscriptionExt::sample) treat each topic separately and
+//! hold back at most its newest envelope, much like
+//! [`DeliveryMode::Latest`](crate::DeliveryMode::Latest), so a busy topic
+//! never hides the last value of a quiet one.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use tokio::time::{Instant, Interval, MissedTickBehavior, Sleep};
+
+use crate::{Envelope, Payload};
+
+/// Operators for streams of [`Envelope`]s.
+///
+/// `map` shares its name with `StreamExt::map` from `futures`; call it as
+/// `SubscriptionExt::map(stream, f)` if both traits are in scope.
+pub trait SubscriptionExt: Stream<Item = Envelope> + Unpin + Sized {
+    /// Pass an envelope through at most once per `interval` on each topic.
+    ///
+    /// The first envelope is delivered immediately. Envelopes arriving
+    /// during the following `interval` are held back, and the newest of
+    /// them is delivered when it ends, so the final value is never lost.
+    fn throttle(self, interval: Duration) -> Throttle<Self> {
+        Throttle {
+            stream: self,
+            interval,
+            windows: HashMap::new(),
+            ready: VecDeque::new(),
+            timer: None,
+            done: false,
+        }
+    }
+
+    /// Deliver an envelope only once no newer one has arrived on its topic
+    /// for `quiet`.
+    fn debounce(self, quiet: Duration) -> Debounce<Self> {
+        Debounce {
+            stream: self,
+            quiet,
+            pending: HashMap::new(),
+            ready: VecDeque::new(),
+            timer: None,
+            done: false,
+        }
+    }
+
+    /// Deliver the newest envelope received on each topic in each `period`,
+    /// and nothing for topics without one. A zero `period` delivers the newest envelope
+    /// as soon as it arrives.
+    fn sample(self, period: Duration) -> Sample<Self> {
+        Sample {
+            stream: self,
+            period,
+            ticker: None,
+            latest: HashMap::new(),
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Skip envelopes whose payload equals the last one delivered on the
+    /// same topic. Expiry notifications always pass.
+    fn distinct(self) -> Distinct<Self> {
+        Distinct {
+            stream: self,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Transform each envelope.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        F: FnMut(Envelope) -> T + Unpin,
+    {
+        Map { stream: self, f }
+    }
+}
+
+impl<S: Stream<Item = Envelope> + Unpin> SubscriptionExt for S {}
+
+/// Pull every envelope that is ready into `each`, returning whether the
+/// stream has ended.
+fn pull<S: Stream<Item = Envelope> + Unpin>(
+    stream: &mut S,
+    cx: &mut Context<'_>,
+    mut each: impl FnMut(Envelope),
+) -> bool {
+    loop {
+        match Pin::new(&mut *stream).poll_next(cx) {
+            Poll::Ready(Some(envelope)) => each(envelope),
+            Poll::Ready(None) => return true,
+            Poll::Pending => return false,
+        }
+    }
+}
+
+/// Point `timer` at `deadline` and register for its wake-up, returning
+/// whether the deadline has already passed.
+fn arm(timer: &mut Option<Pin<Box<Sleep>>>, deadline: Instant, cx: &mut Context<'_>) -> bool {
+    let timer = timer.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+    if timer.deadline() != deadline {
+        timer.as_mut().reset(deadline);
+    }
+    timer.as_mut().poll(cx).is_ready()
+}
+
+/// Stream returned by [`SubscriptionExt::throttle`].
+pub struct Throttle<S> {
+    stream: S,
+    interval: Duration,
+    /// When each topic's current window ends, and the newest envelope held
+    /// back during it.
+    windows: HashMap<Arc<str>, (Instant, Option<Envelope>)>,
+    ready: VecDeque<Envelope>,
+    timer: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S: Stream<Item = Envelope> + Unpin> Stream for Throttle<S> {
+    type Item = Envelope;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        let this = self.get_mut();
+        loop {
+            // Release what was held back through windows that have ended,
+            // starting a new window for each, and close idle ones.
+            let now = Instant::now();
+            this.windows.retain(|_, (end, pending)| {
+                if *end > now {
+                    return true;
+                }
+                let Some(envelope) = pending.take() else {
+                    return false;
+                };
+                this.ready.push_back(envelope);
+                *end = now + this.interval;
+                true
+            });
+
+            if !this.done {
+                this.done = pull(&mut this.stream, cx, |envelope| {
+                    match this.windows.get_mut(&envelope.topic) {
+                        Some((_, pending)) => *pending = Some(envelope),
+                        None => {
+                            let window = (now + this.interval, None);
+                            this.windows.insert(Arc::clone(&envelope.topic), window);
+                            this.ready.push_back(envelope);
+                        }
+                    }
+                });
+            }
+            if this.done {
+                let held = this.windows.drain().filter_map(|(_, (_, pending))| pending);
+                this.ready.extend(held);
+                return Poll::Ready(this.ready.pop_front());
+            }
+            if let Some(envelope) = this.ready.pop_front() {
+                return Poll::Ready(Some(envelope));
+            }
+
+            let Some(end) = this.windows.values().map(|(end, _)| *end).min() else {
+                return Poll::Pending;
+            };
+            if !arm(&mut this.timer, end, cx) {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// Stream returned by [`SubscriptionExt::debounce`].
+pub struct Debounce<S> {
+    stream: S,
+    quiet: Duration,
+    /// Each topic's newest envelope and when it is due.
+    pending: HashMap<Arc<str>, (Instant, Envelope)>,
+    ready: VecDeque<Envelope>,
+    timer: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S: Stream<Item = Envelope> + Unpin> Stream for Debounce<S> {
+    type Item = Envelope;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        let this = self.get_mut();
+        loop {
+            let now = Instant::now();
+            if !this.done {
+                this.done = pull(&mut this.stream, cx, |envelope| {
+                    let due = (now + this.quiet, envelope);
+                    this.pending.insert(Arc::clone(&due.1.topic), due);
+                });
+            }
+            if this.done {
+                let mut held: Vec<_> = this.pending.drain().map(|(_, due)| due).collect();
+                held.sort_by_key(|(due, _)| *due);
+                this.ready
+                    .extend(held.into_iter().map(|(_, envelope)| envelope));
+                return Poll::Ready(this.ready.pop_front());
+            }
+
+            let settled = this.pending.extract_if(|_, (due, _)| *due <= now);
+            this.ready
+                .extend(settled.map(|(_, (_, envelope))| envelope));
+            if let Some(envelope) = this.ready.pop_front() {
+                return Poll::Ready(Some(envelope));
+            }
+
+            let Some(due) = this.pending.values().map(|(due, _)| *due).min() else {
+                return Poll::Pending;
+            };
+            if !arm(&mut this.timer, due, cx) {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// Stream returned by [`SubscriptionExt::sample`].
+pub struct Sample<S> {
+    stream: S,
+    period: Duration,
+    ticker: Option<Interval>,
+    /// Each topic's newest envelope since the last tick.
+    latest: HashMap<Arc<str>, Envelope>,
+    ready: VecDeque<Envelope>,
+    done: bool,
+}
+
+impl<S: Stream<Item = Envelope> + Unpin> Stream for Sample<S> {
+    type Item = Envelope;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        let this = self.get_mut();
+        if !this.done {
+            this.done = pull(&mut this.stream, cx, |envelope| {
+                this.latest.insert(Arc::clone(&envelope.topic), envelope);
+            });
+        }
+        if this.done {
+            this.ready
+                .extend(this.latest.drain().map(|(_, envelope)| envelope));
+            return Poll::Ready(this.ready.pop_front());
+        }
+        if let Some(envelope) = this.ready.pop_front() {
+            return Poll::Ready(Some(envelope));
+        }
+
+        // The ticker is only polled while something is waiting, so an idle
+        // stream does not wake up every period.
+        if !this.latest.is_empty() {
+            let period = this.period;
+            let tick = period.is_zero()
+                || this
+                    .ticker
+                    .get_or_insert_with(|| {
+                        let mut ticker = tokio::time::interval_at(Instant::now() + period, period);
+                        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                        ticker
+                    })
+                    .poll_tick(cx)
+                    .is_ready();
+            if tick {
+                this.ready
+                    .extend(this.latest.drain().map(|(_, envelope)| envelope));
+                return Poll::Ready(this.ready.pop_front());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Stream returned by [`SubscriptionExt::distinct`].
+pub struct Distinct<S> {
+    stream: S,
+    last: HashMap<Arc<str>, Payload>,
+}
+
+impl<S: Stream<Item = Envelope> + Unpin> Stream for Distinct<S> {
+    type Item = Envelope;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+        let this = self.get_mut();
+        loop {
+            let Some(envelope) = std::task::ready!(Pin::new(&mut this.stream).poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            if envelope.is_expired() {
+                this.last.remove(&envelope.topic);
+                return Poll::Ready(Some(envelope));
+            }
+            if this.last.get(&envelope.topic) != Some(&envelope.payload) {
+                this.last
+                    .insert(Arc::clone(&envelope.topic), envelope.payload.clone());
+                return Poll::Ready(Some(envelope));
+            }
+        }
+    }
+}
+
+/// Stream returned by [`SubscriptionExt::map`].
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S, F, T> Stream for Map<S, F>
+where
+    S: Stream<Item = Envelope> + Unpin,
+    F: FnMut(Envelope) -> T + Unpin,
+{
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        Pin::new(&mut this.stream)
+            .poll_next(cx)
+            .map(|envelope| envelope.map(&mut this.f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Bus, DeliveryMode};
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    /// Whether the stream stays quiet for `wait`.
+    async fn idle<S: Stream + Unpin>(stream: &mut S, wait: Duration) -> bool {
+        tokio::time::timeout(wait, next(stream)).await.is_err()
+    }
+
+    fn text(envelope: Option<Envelope>) -> String {
+        envelope.unwrap().payload.as_str().unwrap().to_string()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttle_delivers_first_and_last() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("fps", DeliveryMode::queue(64))
+            .unwrap()
+            .throttle(Duration::from_millis(100));
+
+        (0..10).for_each(|i| {
+            bus.publish("fps", i.to_string()).unwrap();
+        });
+        assert_eq!(text(next(&mut sub).await), "0");
+        let start = Instant::now();
+        assert_eq!(text(next(&mut sub).await), "9");
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert!(idle(&mut sub, Duration::from_millis(500)).await);
+
+        // A quiet window closes, so the next envelope is immediate again.
+        bus.publish("fps", "10").unwrap();
+        let start = Instant::now();
+        assert_eq!(text(next(&mut sub).await), "10");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    /// The next `n` envelopes as sorted `topic=payload` strings.
+    async fn take<S: Stream<Item = Envelope> + Unpin>(stream: &mut S, n: usize) -> Vec<String> {
+        let mut taken = Vec::new();
+        for _ in 0..n {
+            let envelope = next(stream).await.unwrap();
+            taken.push(format!(
+                "{}={}",
+                envelope.topic,
+                envelope.payload.as_str().unwrap()
+            ));
+        }
+        taken.sort();
+        taken
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn holds_back_newest_per_topic() {
+        let bus = Bus::new();
+        let subscribe = || {
+            bus.subscribe_with("game/*/health", DeliveryMode::queue(64))
+                .unwrap()
+        };
+        let period = Duration::from_millis(100);
+        let mut throttled = subscribe().throttle(period);
+        let mut debounced = subscribe().debounce(period);
+        let mut sampled = subscribe().sample(period);
+
+        for (player, hp) in [("a", "100"), ("b", "100"), ("a", "90"), ("b", "80")] {
+            bus.publish(format!("game/{player}/health"), hp).unwrap();
+        }
+        let last = ["game/a/health=90", "game/b/health=80"];
+        assert_eq!(
+            take(&mut throttled, 2).await,
+            ["game/a/health=100", "game/b/health=100"]
+        );
+        assert_eq!(take(&mut throttled, 2).await, last);
+        assert_eq!(take(&mut debounced, 2).await, last);
+        assert_eq!(take(&mut sampled, 2).await, last);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn debounce_waits_for_quiet() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("search", DeliveryMode::queue(64))
+            .unwrap()
+            .debounce(Duration::from_millis(50));
+
+        for query in ["r", "re", "rec"] {
+            bus.publish("search", query).unwrap();
+            assert!(idle(&mut sub, Duration::from_millis(30)).await);
+        }
+        assert_eq!(text(next(&mut sub).await), "rec");
+        assert!(idle(&mut sub, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sample_delivers_newest_per_period() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("pos", DeliveryMode::queue(64))
+            .unwrap()
+            .sample(Duration::from_millis(100));
+
+        bus.publish("pos", "1").unwrap();
+        bus.publish("pos", "2").unwrap();
+        assert_eq!(text(next(&mut sub).await), "2");
+        assert!(idle(&mut sub, Duration::from_millis(250)).await);
+        bus.publish("pos", "3").unwrap();
+        assert_eq!(text(next(&mut sub).await), "3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_sample_period_passes_through() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("pos", DeliveryMode::queue(64))
+            .unwrap()
+            .sample(Duration::ZERO);
+
+        bus.publish("pos", "1").unwrap();
+        let start = Instant::now();
+        assert_eq!(text(next(&mut sub).await), "1");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn distinct_per_topic() {
+        let bus = Bus::new();
+        let mut sub = bus
+            .subscribe_with("hp/*", DeliveryMode::queue(64))
+            .unwrap()
+            .distinct()
+            .map(|envelope| format!("{}={}", envelope.topic, envelope.payload.as_str().unwrap()));
+
+        for (topic, hp) in [
+            ("hp/a", "100"),
+            ("hp/a", "100"),
+            ("hp/b", "100"),
+            ("hp/a", "90"),
+        ] {
+            bus.publish(topic, hp).unwrap();
+        }
+        assert_eq!(next(&mut sub).await.unwrap(), "hp/a=100");
+        assert_eq!(next(&mut sub).await.unwrap(), "hp/b=100");
+        assert_eq!(next(&mut sub).await.unwrap(), "hp/a=90");
+        assert!(idle(&mut sub, Duration::from_millis(10)).await);
+    }
+
+    /// A finite stream, since a subscription only ends with its bus.
+    struct Envelopes(std::vec::IntoIter<Envelope>);
+
+    impl Stream for Envelopes {
+        type Item = Envelope;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Envelope>> {
+            Poll::Ready(self.get_mut().0.next())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flushes_held_envelope_when_stream_ends() {
+        let envelopes = || {
+            let envelopes: Vec<_> = ["58", "59", "60"]
+                .into_iter()
+                .map(|fps| Envelope::new(Arc::from("fps"), fps))
+                .collect();
+            Envelopes(envelopes.into_iter())
+        };
+
+        let mut debounced = envelopes().debounce(Duration::from_secs(60));
+        assert_eq!(text(next(&mut debounced).await), "60");
+        assert!(next(&mut debounced).await.is_none());
+
+        let mut throttled = envelopes().throttle(Duration::from_secs(60));
+        assert_eq!(text(next(&mut throttled).await), "58");
+        assert_eq!(text(next(&mut throttled).await), "60");
+        assert!(next(&mut throttled).await.is_none());
+    }
+}
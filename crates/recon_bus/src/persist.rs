@@ -0,0 +1,329 @@
+//! Saving retained state to disk and restoring it on startup.
+//!
+//! Only retained envelopes on topics matching the configured filters are
+//! saved. Envelopes with a TTL describe transient state and are skipped.
+//! The snapshot file is rewritten atomically via a temporary file, so a
+//! crash mid-write leaves the previous snapshot intact.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Bus, Headers, JournalPayload, PublishOptions, Topic, TopicError, topic_matches};
+
+/// Snapshot file format version.
+const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    topics: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotEntry {
+    topic: String,
+    #[serde(flatten)]
+    payload: JournalPayload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publisher: Option<String>,
+    #[serde(default, skip_serializing_if = "Headers::is_empty")]
+    headers: Headers,
+}
+
+/// Which retained topics to persist, and where.
+///
+/// ```ignore
+/// let persistence = Persistence::new("bus_state.json")
+///     .filter("settings/**")?
+///     .filter("profile/**")?;
+/// persistence.restore(&bus)?;
+/// let _saver = persistence.start(&bus);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Persistence {
+    path: PathBuf,
+    filters: Vec<Topic>,
+    interval: Duration,
+}
+
+impl Persistence {
+    /// Persist to `path`, flushing every five seconds. No topics are saved
+    /// until at least one filter is added.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            filters: Vec::new(),
+            interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Also persist retained topics matching `filter`.
+    pub fn filter(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Self, TopicError> {
+        self.filters.push(filter.try_into()?);
+        Ok(self)
+    }
+
+    /// How often the background saver checks for changes.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Republish every saved topic as retained state, returning how many
+    /// were restored.
+    ///
+    /// Call before anything subscribes so every subscriber is seeded with
+    /// the restored state. A missing file restores nothing; topics in the
+    /// file that no longer match a filter are ignored.
+    pub fn restore(&self, bus: &Bus) -> io::Result<usize> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let snapshot: Snapshot = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if snapshot.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported bus snapshot version {}", snapshot.version),
+            ));
+        }
+
+        let mut restored = 0;
+        for entry in snapshot.topics {
+            let Ok(topic) = Topic::try_from(entry.topic.as_str()) else {
+                continue;
+            };
+            if !self.filters.iter().any(|f| topic_matches(f, &topic)) {
+                continue;
+            }
+            let mut options = PublishOptions::new().retain();
+            options.publisher = entry.publisher.map(Into::into);
+            options.headers = entry.headers;
+            bus.publish_with(&topic, entry.payload, options)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Write the current retained state to disk now.
+    pub fn save(&self, bus: &Bus) -> io::Result<()> {
+        self.write(&self.snapshot(bus))
+    }
+
+    /// Save in a background thread every [`interval`](Self::interval) while
+    /// anything changed, and once more when the returned handle drops.
+    pub fn start(self, bus: &Bus) -> PersistenceHandle {
+        let shared = Arc::new(Shared {
+            persistence: self,
+            bus: bus.clone(),
+            stop: AtomicBool::new(false),
+            saved: Mutex::new(None),
+        });
+        let thread = thread::Builder::new()
+            .name("recon-bus-persist".into())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || shared.run()
+            })
+            .expect("failed to spawn persistence thread");
+        PersistenceHandle {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn snapshot(&self, bus: &Bus) -> Vec<SnapshotEntry> {
+        let mut topics = BTreeMap::new();
+        self.filters
+            .iter()
+            .flat_map(|filter| bus.retained_matching(filter))
+            .filter(|envelope| envelope.expires_at.is_none())
+            .for_each(|envelope| {
+                let entry = SnapshotEntry {
+                    topic: envelope.topic.to_string(),
+                    payload: envelope.payload.into(),
+                    publisher: envelope.publisher.as_deref().map(str::to_string),
+                    headers: envelope.headers.as_deref().cloned().unwrap_or_default(),
+                };
+                topics.insert(entry.topic.clone(), entry);
+            });
+        topics.into_values().collect()
+    }
+
+    fn write(&self, topics: &[SnapshotEntry]) -> io::Result<()> {
+        let snapshot = Snapshot {
+            version: VERSION,
+            topics: topics.to_vec(),
+        };
+        let json = serde_json::to_vec_pretty(&snapshot).map_err(io::Error::other)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+struct Shared {
+    persistence: Persistence,
+    bus: Bus,
+    stop: AtomicBool,
+    /// Last snapshot written. The lock also serializes writes.
+    saved: Mutex<Option<Vec<SnapshotEntry>>>,
+}
+
+impl Shared {
+    fn run(&self) {
+        loop {
+            let stopping = self.stop.load(Ordering::Relaxed);
+            let _ = self.save_if_changed();
+            if stopping {
+                break;
+            }
+            thread::park_timeout(self.persistence.interval);
+        }
+    }
+
+    fn save_if_changed(&self) -> io::Result<()> {
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.persistence.snapshot(&self.bus);
+        if saved.as_ref() == Some(&current) {
+            return Ok(());
+        }
+        self.persistence.write(&current)?;
+        *saved = Some(current);
+        Ok(())
+    }
+}
+
+/// Handle to the background saver from [`Persistence::start`]. Dropping it
+/// saves one final time.
+pub struct PersistenceHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PersistenceHandle {
+    /// Write the current retained state to disk now, if it changed since
+    /// the last save.
+    pub fn flush(&self) -> io::Result<()> {
+        self.shared.save_if_changed()
+    }
+}
+
+impl Drop for PersistenceHandle {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("recon-bus-{}-{name}.json", std::process::id()))
+    }
+
+    fn persistence(path: &Path) -> Persistence {
+        Persistence::new(path)
+            .filter("settings/**")
+            .unwrap()
+            .filter("profile/**")
+            .unwrap()
+    }
+
+    #[test]
+    fn saves_and_restores_selected_topics() {
+        let path = temp_path("roundtrip");
+        let bus = Bus::new();
+        bus.publish_retained("settings/theme", "dark").unwrap();
+        bus.publish_with(
+            "profile/avatar",
+            vec![1u8, 2],
+            PublishOptions::new()
+                .retain()
+                .publisher("profile")
+                .header("content-type", "image/png"),
+        )
+        .unwrap();
+        bus.publish_with(
+            "settings/session",
+            "abc",
+            PublishOptions::new().retain().ttl(Duration::from_secs(60)),
+        )
+        .unwrap();
+        bus.publish_retained("game/apex/status", "online").unwrap();
+        persistence(&path).save(&bus).unwrap();
+
+        let restored = Bus::new();
+        assert_eq!(persistence(&path).restore(&restored).unwrap(), 2);
+        let theme = restored.retained("settings/theme").unwrap();
+        assert_eq!(theme[0].payload, "dark");
+        let avatar = &restored.retained("profile/avatar").unwrap()[0];
+        assert_eq!(avatar.payload.as_bytes(), [1, 2]);
+        assert_eq!(avatar.publisher.as_deref(), Some("profile"));
+        assert_eq!(avatar.header("content-type"), Some("image/png"));
+        assert!(restored.retained("game/**").unwrap().is_empty());
+
+        // Narrower filters on restore ignore the rest of the file.
+        let narrow = Persistence::new(&path).filter("profile/**").unwrap();
+        assert_eq!(narrow.restore(&Bus::new()).unwrap(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_restores_nothing() {
+        let path = temp_path("missing");
+        assert_eq!(persistence(&path).restore(&Bus::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn background_saver_flushes_on_drop() {
+        let path = temp_path("background");
+        let bus = Bus::new();
+        let saver = persistence(&path)
+            .interval(Duration::from_secs(3600))
+            .start(&bus);
+
+        bus.publish_retained("settings/volume", "80").unwrap();
+        saver.flush().unwrap();
+        let saved = Bus::new();
+        persistence(&path).restore(&saved).unwrap();
+        assert_eq!(saved.retained("settings/volume").unwrap()[0].payload, "80");
+
+        bus.publish_retained("settings/volume", "40").unwrap();
+        drop(saver);
+        let saved = Bus::new();
+        persistence(&path).restore(&saved).unwrap();
+        assert_eq!(saved.retained("settings/volume").unwrap()[0].payload, "40");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,565 @@
+//! Content predicates over JSON payloads.
+//!
+//! A predicate compares values at JSON pointers (RFC 6901) in a payload:
+//!
+//! ```text
+//! /killer == "me" && /damage >= 50
+//! /weapon in ["vandal", "phantom"] || !(/headshot)
+//! ```
+//!
+//! Comparisons are `==`, `!=`, `<`, `<=`, `>` and `>=`; `in` tests
+//! membership in a list of literals. Terms combine with `!`, `&&` and `||`
+//! (`&&` binds tighter) and parentheses. A bare pointer is true when the
+//! value exists and is neither `false` nor `null`.
+//!
+//! Any comparison against a missing value is false, including `!=`.
+//! Numbers compare by value, so `1 == 1.0`; ordering works on numbers and
+//! on strings, and is false for any other pair.
+
+use std::{cell::OnceCell, cmp::Ordering, fmt, str::FromStr, sync::Arc};
+
+use serde_json::Value;
+
+use crate::Payload;
+
+/// A parsed payload predicate. Cloning is cheap.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    source: Arc<str>,
+    expr: Arc<Expr>,
+}
+
+impl Predicate {
+    pub fn parse(source: &str) -> Result<Self, PredicateError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            end: source.len(),
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some((at, _)) = parser.peek() {
+            return Err(PredicateError::new(
+                at,
+                "expected '&&', '||' or end of input",
+            ));
+        }
+        Ok(Self {
+            source: Arc::from(source),
+            expr: Arc::new(expr),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluate the predicate against a JSON document.
+    pub fn matches(&self, value: &Value) -> bool {
+        self.expr.eval(value)
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = PredicateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<&str> for Predicate {
+    type Error = PredicateError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for Predicate {
+    type Error = PredicateError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+impl TryFrom<&Predicate> for Predicate {
+    type Error = PredicateError;
+
+    fn try_from(p: &Predicate) -> Result<Self, Self::Error> {
+        Ok(p.clone())
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A predicate failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredicateError {
+    /// Byte offset into the source.
+    pub position: usize,
+    pub message: String,
+}
+
+impl PredicateError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PredicateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid predicate at byte {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for PredicateError {}
+
+/// A payload parsed as JSON at most once, however many predicates read it.
+pub(crate) struct PayloadJson<'a> {
+    payload: &'a Payload,
+    value: OnceCell<Option<Value>>,
+}
+
+impl<'a> PayloadJson<'a> {
+    pub fn new(payload: &'a Payload) -> Self {
+        Self {
+            payload,
+            value: OnceCell::new(),
+        }
+    }
+
+    /// Whether the payload is JSON and satisfies `predicate`.
+    pub fn matches(&self, predicate: &Predicate) -> bool {
+        self.value
+            .get_or_init(|| serde_json::from_slice(self.payload.as_bytes()).ok())
+            .as_ref()
+            .is_some_and(|value| predicate.matches(value))
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Truthy(String),
+    Compare {
+        pointer: String,
+        op: CompareOp,
+        value: Value,
+    },
+    In {
+        pointer: String,
+        values: Vec<Value>,
+    },
+}
+
+impl Expr {
+    fn eval(&self, doc: &Value) -> bool {
+        match self {
+            Self::And(a, b) => a.eval(doc) && b.eval(doc),
+            Self::Or(a, b) => a.eval(doc) || b.eval(doc),
+            Self::Not(e) => !e.eval(doc),
+            Self::Truthy(pointer) => doc
+                .pointer(pointer)
+                .is_some_and(|v| !matches!(v, Value::Null | Value::Bool(false))),
+            Self::Compare { pointer, op, value } => {
+                doc.pointer(pointer).is_some_and(|v| op.apply(v, value))
+            }
+            Self::In { pointer, values } => doc
+                .pointer(pointer)
+                .is_some_and(|v| values.iter().any(|candidate| json_eq(v, candidate))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn apply(self, a: &Value, b: &Value) -> bool {
+        match self {
+            Self::Eq => json_eq(a, b),
+            Self::Ne => !json_eq(a, b),
+            Self::Lt => json_cmp(a, b).is_some_and(Ordering::is_lt),
+            Self::Le => json_cmp(a, b).is_some_and(Ordering::is_le),
+            Self::Gt => json_cmp(a, b).is_some_and(Ordering::is_gt),
+            Self::Ge => json_cmp(a, b).is_some_and(Ordering::is_ge),
+        }
+    }
+}
+
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+fn json_cmp(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Pointer(String),
+    Literal(Value),
+    Compare(CompareOp),
+    In,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+/// Characters that end a JSON pointer token.
+const POINTER_END: &[char] = &['=', '!', '<', '>', '(', ')', '[', ']', ',', '&', '|'];
+
+fn lex(source: &str) -> Result<Vec<(usize, Token)>, PredicateError> {
+    let mut tokens = Vec::new();
+    let mut rest = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = rest.peek() {
+        if c.is_whitespace() {
+            rest.next();
+            continue;
+        }
+
+        let remaining = &source[start..];
+        let (token, len) = if c == '/' {
+            let len = remaining
+                .find(|c: char| c.is_whitespace() || POINTER_END.contains(&c))
+                .unwrap_or(remaining.len());
+            (Token::Pointer(remaining[..len].to_string()), len)
+        } else if c == '"' {
+            let len = string_len(remaining)
+                .ok_or_else(|| PredicateError::new(start, "unterminated string"))?;
+            let value = serde_json::from_str(&remaining[..len])
+                .map_err(|e| PredicateError::new(start, e.to_string()))?;
+            (Token::Literal(value), len)
+        } else if c == '-' || c.is_ascii_digit() {
+            let len = remaining
+                .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                .unwrap_or(remaining.len());
+            let value = serde_json::from_str(&remaining[..len])
+                .map_err(|_| PredicateError::new(start, "invalid number"))?;
+            (Token::Literal(value), len)
+        } else if c.is_ascii_alphabetic() {
+            let len = remaining
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(remaining.len());
+            let token = match &remaining[..len] {
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                "in" => Token::In,
+                word => {
+                    return Err(PredicateError::new(
+                        start,
+                        format!("unexpected '{word}', pointers start with '/'"),
+                    ));
+                }
+            };
+            (token, len)
+        } else {
+            let symbols = [
+                ("==", Token::Compare(CompareOp::Eq)),
+                ("!=", Token::Compare(CompareOp::Ne)),
+                ("<=", Token::Compare(CompareOp::Le)),
+                (">=", Token::Compare(CompareOp::Ge)),
+                ("<", Token::Compare(CompareOp::Lt)),
+                (">", Token::Compare(CompareOp::Gt)),
+                ("&&", Token::And),
+                ("||", Token::Or),
+                ("!", Token::Not),
+                ("(", Token::LParen),
+                (")", Token::RParen),
+                ("[", Token::LBracket),
+                ("]", Token::RBracket),
+                (",", Token::Comma),
+            ];
+            symbols
+                .into_iter()
+                .find(|(symbol, _)| remaining.starts_with(symbol))
+                .map(|(symbol, token)| (token, symbol.len()))
+                .ok_or_else(|| PredicateError::new(start, format!("unexpected '{c}'")))?
+        };
+
+        tokens.push((start, token));
+        while rest.next_if(|&(i, _)| i < start + len).is_some() {}
+    }
+
+    Ok(tokens)
+}
+
+/// Byte length of the JSON string literal at the start of `s`, quotes
+/// included.
+fn string_len(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    s.char_indices().skip(1).find_map(|(i, c)| {
+        match c {
+            '"' if !escaped => return Some(i + 1),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+        None
+    })
+}
+
+/// How deep `!`, parentheses and chained `&&` / `||` may nest. Predicates
+/// come from plugins, so deeper input is rejected rather than allowed to
+/// overflow the stack while parsing or evaluating.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    pos: usize,
+    /// Source length, reported as the position of a missing token.
+    end: usize,
+    /// Levels of expression tree above the current token.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens.get(self.pos).map(|(at, t)| (*at, t))
+    }
+
+    fn next(&mut self) -> Result<(usize, &Token), PredicateError> {
+        let (at, token) = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| PredicateError::new(self.end, "unexpected end of input"))?;
+        self.pos += 1;
+        Ok((*at, token))
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek().is_some_and(|(_, t)| t == token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &Token, what: &str) -> Result<(), PredicateError> {
+        let (at, found) = self.next()?;
+        if found == token {
+            Ok(())
+        } else {
+            Err(PredicateError::new(at, format!("expected {what}")))
+        }
+    }
+
+    /// Enter one more level below the token just consumed.
+    fn descend(&mut self) -> Result<(), PredicateError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let at = self.tokens[self.pos - 1].0;
+            return Err(PredicateError::new(
+                at,
+                format!("nested more than {MAX_DEPTH} levels deep"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, PredicateError> {
+        let depth = self.depth;
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            self.descend()?;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, PredicateError> {
+        let depth = self.depth;
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            self.descend()?;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, PredicateError> {
+        let (at, token) = self.next()?;
+        match token {
+            Token::Not => {
+                self.descend()?;
+                let expr = self.unary()?;
+                self.depth -= 1;
+                Ok(Expr::Not(Box::new(expr)))
+            }
+            Token::LParen => {
+                self.descend()?;
+                let expr = self.or()?;
+                self.expect(&Token::RParen, "')'")?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Token::Pointer(pointer) => {
+                let pointer = pointer.clone();
+                self.comparison(pointer)
+            }
+            _ => Err(PredicateError::new(at, "expected a pointer, '!' or '('")),
+        }
+    }
+
+    fn comparison(&mut self, pointer: String) -> Result<Expr, PredicateError> {
+        match self.peek() {
+            Some((_, &Token::Compare(op))) => {
+                self.pos += 1;
+                let value = self.literal()?;
+                Ok(Expr::Compare { pointer, op, value })
+            }
+            Some((_, Token::In)) => {
+                self.pos += 1;
+                self.expect(&Token::LBracket, "'['")?;
+                let mut values = vec![self.literal()?];
+                while self.eat(&Token::Comma) {
+                    values.push(self.literal()?);
+                }
+                self.expect(&Token::RBracket, "',' or ']'")?;
+                Ok(Expr::In { pointer, values })
+            }
+            _ => Ok(Expr::Truthy(pointer)),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, PredicateError> {
+        match self.next()? {
+            (_, Token::Literal(value)) => Ok(value.clone()),
+            (at, _) => Err(PredicateError::new(
+                at,
+                "expected a string, number, boolean or null",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn eval(predicate: &str, doc: Value) -> bool {
+        Predicate::parse(predicate).unwrap().matches(&doc)
+    }
+
+    #[test]
+    fn comparisons() {
+        let doc = json!({ "killer": "me", "damage": 75, "pos": { "x": 1.5 } });
+        assert!(eval(r#"/killer == "me""#, doc.clone()));
+        assert!(eval(r#"/killer != "you""#, doc.clone()));
+        assert!(eval("/damage >= 75", doc.clone()));
+        assert!(!eval("/damage < 75", doc.clone()));
+        assert!(eval("/damage == 75.0", doc.clone()));
+        assert!(eval("/pos/x > 1", doc.clone()));
+        assert!(eval(r#"/killer < "you""#, doc.clone()));
+        assert!(!eval(r#"/damage < "80""#, doc));
+    }
+
+    #[test]
+    fn missing_values_never_match() {
+        let doc = json!({ "a": 1 });
+        assert!(!eval("/b == 1", doc.clone()));
+        assert!(!eval("/b != 1", doc.clone()));
+        assert!(!eval("/b in [1]", doc.clone()));
+        assert!(eval("!/b", doc));
+    }
+
+    #[test]
+    fn membership_and_logic() {
+        let doc = json!({ "weapon": "vandal", "headshot": false, "round": 3 });
+        assert!(eval(r#"/weapon in ["phantom", "vandal"]"#, doc.clone()));
+        assert!(eval(r#"/round in [1, 2, 3] && !/headshot"#, doc.clone()));
+        assert!(eval(
+            r#"/round == 1 || /round == 2 || /round == 3"#,
+            doc.clone()
+        ));
+        // `&&` binds tighter than `||`.
+        assert!(eval("/round == 3 || /round == 1 && /headshot", doc.clone()));
+        assert!(!eval("(/round == 3 || /round == 1) && /headshot", doc));
+    }
+
+    #[test]
+    fn pointer_escapes() {
+        let doc = json!({ "a/b": { "c~d": true } });
+        assert!(eval("/a~1b/c~0d", doc.clone()));
+        assert!(eval("/a~1b/c~0d == true", doc));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = Predicate::parse("killer == 1").unwrap_err();
+        assert_eq!(err.position, 0);
+
+        let err = Predicate::parse(r#"/a == "open"#).unwrap_err();
+        assert_eq!(err.position, 6);
+
+        let err = Predicate::parse("/a ==").unwrap_err();
+        assert_eq!(err.position, 5);
+
+        assert!(Predicate::parse("/a in [1, 2").is_err());
+        assert!(Predicate::parse("/a == 1 /b").is_err());
+        assert!(Predicate::parse("(/a").is_err());
+        assert!(Predicate::parse("/a === 1").is_err());
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let err = Predicate::parse(&format!("{}/a", "!".repeat(100_000))).unwrap_err();
+        assert_eq!(err.position, MAX_DEPTH);
+        let parens = |n| format!("{}/a{}", "(".repeat(n), ")".repeat(n));
+        assert!(Predicate::parse(&parens(MAX_DEPTH)).is_ok());
+        assert!(Predicate::parse(&parens(MAX_DEPTH + 1)).is_err());
+        let chain = |n| vec!["/a"; n].join(" && ");
+        assert!(Predicate::parse(&chain(MAX_DEPTH + 1)).is_ok());
+        assert!(Predicate::parse(&chain(100_000)).is_err());
+    }
+
+    #[test]
+    fn non_json_payload_fails() {
+        let predicate = Predicate::parse("/a").unwrap();
+        let payload = Payload::from("not json");
+        assert!(!PayloadJson::new(&payload).matches(&predicate));
+
+        let payload = Payload::from(r#"{"a": true}"#);
+        assert!(PayloadJson::new(&payload).matches(&predicate));
+    }
+}
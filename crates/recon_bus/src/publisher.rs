@@ -0,0 +1,141 @@
+//! Prepared publisher handles for hot publish paths.
+
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::{Bus, BusError, Payload, Topic, TopicError, trie::SubscriberId};
+
+/// Subscribers matching the publisher's topic as of a routing generation.
+#[derive(Debug)]
+struct CachedRoute {
+    generation: u64,
+    subscribers: Box<[SubscriberId]>,
+}
+
+/// A handle for publishing repeatedly to one concrete topic.
+///
+/// The topic is parsed once, and the matching subscribers are cached until
+/// the bus's subscriptions change, so a steady-state publish skips both
+/// topic validation and the trie walk.
+pub struct Publisher {
+    bus: Bus,
+    topic: Topic,
+    route: ArcSwap<CachedRoute>,
+}
+
+impl Bus {
+    /// Prepare a [`Publisher`] for `topic`.
+    pub fn publisher(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Publisher, BusError> {
+        let topic = topic.try_into()?;
+        let route = ArcSwap::from_pointee(Publisher::resolve(self, &topic));
+        Ok(Publisher {
+            bus: self.clone(),
+            topic,
+            route,
+        })
+    }
+}
+
+impl Publisher {
+    pub fn topic(&self) -> &Topic {
+        &self.topic
+    }
+
+    /// Publish a string payload.
+    pub fn publish(&self, payload: impl Into<String>) -> Result<usize, BusError> {
+        let payload: Arc<str> = Arc::from(payload.into());
+        self.send(Payload::Text(payload))
+    }
+
+    /// Publish a binary payload.
+    pub fn publish_bytes(&self, payload: impl Into<Arc<[u8]>>) -> Result<usize, BusError> {
+        self.send(Payload::Bytes(payload.into()))
+    }
+
+    /// Publish a serializable value as JSON.
+    #[cfg(feature = "serde")]
+    pub fn publish_serde(
+        &self,
+        value: &(impl ::serde::Serialize + ?Sized),
+    ) -> Result<usize, BusError> {
+        let payload: Arc<str> = Arc::from(serde_json::to_string(value)?);
+        self.send(Payload::Text(payload))
+    }
+
+    fn send(&self, payload: Payload) -> Result<usize, BusError> {
+        let envelope = self.bus.envelope(&self.topic, payload)?;
+
+        let mut route = self.route.load();
+        if route.generation != self.bus.inner.routes.generation() {
+            self.route
+                .store(Arc::new(Self::resolve(&self.bus, &self.topic)));
+            route = self.route.load();
+        }
+
+        self.bus
+            .deliver_to(&self.topic, envelope, &route.subscribers)
+    }
+
+    fn resolve(bus: &Bus, topic: &Topic) -> CachedRoute {
+        // Read the generation first: if the routes change in between, the
+        // cache is merely refreshed once more on the next publish.
+        let generation = bus.inner.routes.generation();
+        let subscribers = bus.inner.routes.load().matching(topic).into();
+        CachedRoute {
+            generation,
+            subscribers,
+        }
+    }
+}
+
+impl Clone for Publisher {
+    fn clone(&self) -> Self {
+        Self {
+            bus: self.bus.clone(),
+            topic: self.topic.clone(),
+            route: ArcSwap::new(self.route.load_full()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publishes_to_existing_subscribers() {
+        let bus = Bus::new();
+        let mut sub = bus.subscribe("game/*/health").unwrap();
+        let publisher = bus.publisher("game/valorant/health").unwrap();
+
+        assert_eq!(publisher.publish("100").unwrap(), 1);
+        assert_eq!(sub.recv().await.unwrap().payload, "100");
+    }
+
+    #[tokio::test]
+    async fn picks_up_subscription_changes() {
+        let bus = Bus::new();
+        let publisher = bus.publisher("telemetry").unwrap();
+        assert_eq!(publisher.publish("1").unwrap(), 0);
+
+        let mut sub = bus.subscribe("telemetry").unwrap();
+        assert_eq!(publisher.publish("2").unwrap(), 1);
+        assert_eq!(sub.recv().await.unwrap().payload, "2");
+
+        let clone = publisher.clone();
+        drop(sub);
+        assert_eq!(publisher.publish("3").unwrap(), 0);
+        assert_eq!(clone.publish("4").unwrap(), 0);
+        assert_eq!(bus.stats().publishes, 4);
+    }
+
+    #[test]
+    fn rejects_invalid_topic() {
+        let bus = Bus::new();
+        assert!(bus.publisher("a//b").is_err());
+    }
+}
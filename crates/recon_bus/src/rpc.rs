@@ -0,0 +1,225 @@
+//! Request/reply on top of the bus.
+//!
+//! A request is a normal publish whose envelope carries a `reply_to` topic.
+//! Reply topics are generated per request as `$reply/<correlation id>`, and
+//! the requester holds a private subscription to it until the reply arrives
+//! or the timeout elapses. Correlation ids are keyed with random per-bus
+//! keys, so a reply topic cannot be guessed from earlier ones.
+
+use std::{future::Future, hash::BuildHasher, sync::atomic::Ordering, time::Duration};
+
+use crate::{
+    Bus, BusError, DeliveryMode, Envelope, Overflow, Topic, TopicError, topic::filters_overlap,
+};
+
+/// Prefix of generated reply topics.
+pub const REPLY_PREFIX: &str = "$reply";
+
+/// Whether `topic` is a generated reply topic.
+pub(crate) fn is_reply_topic(topic: &str) -> bool {
+    topic
+        .strip_prefix(REPLY_PREFIX)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether subscribing with `filter` could receive replies meant for
+/// another requester.
+pub(crate) fn reaches_replies(filter: &Topic) -> bool {
+    let replies = Topic::try_from(format!("{REPLY_PREFIX}/**")).expect("valid filter");
+    filters_overlap(filter, &replies)
+}
+
+/// How many requests a responder buffers before rejecting new ones.
+pub(crate) const RESPONDER_QUEUE: usize = 64;
+
+/// Handle to a running [`Bus::serve`] task. Dropping it stops serving.
+#[derive(Debug)]
+pub struct Responder {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Bus {
+    /// Publish a request and wait for the first reply.
+    ///
+    /// Fails with [`BusError::NoResponder`] if nothing is subscribed to
+    /// `topic`, or [`BusError::Timeout`] if no reply arrives in time.
+    pub async fn request(
+        &self,
+        topic: impl TryInto<Topic, Error = TopicError>,
+        payload: impl Into<String>,
+        timeout: Duration,
+    ) -> Result<Envelope, BusError> {
+        let topic = topic.try_into()?;
+        let count = self.inner.next_request.fetch_add(1, Ordering::Relaxed);
+        let tag = self.inner.reply_keys.hash_one(count);
+        let reply_topic = Topic::try_from(format!("{REPLY_PREFIX}/{count:x}-{tag:016x}"))?;
+        let mut replies = self.subscribe_with(&reply_topic, DeliveryMode::queue(1))?;
+
+        let payload: String = payload.into();
+        let mut envelope = self.envelope(&topic, payload)?;
+        envelope.reply_to = Some(reply_topic.into_arc());
+        if self.deliver(&topic, envelope)? == 0 {
+            return Err(BusError::NoResponder);
+        }
+
+        tokio::time::timeout(timeout, replies.recv())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(BusError::Timeout)
+    }
+
+    /// Publish a reply to a request envelope.
+    pub fn reply(&self, request: &Envelope, payload: impl Into<String>) -> Result<usize, BusError> {
+        let reply_to = request.reply_to.as_deref().ok_or(BusError::NotARequest)?;
+        self.publish(reply_to, payload)
+    }
+
+    /// Answer every request matching `filter` with `handler`.
+    ///
+    /// Requests are handled one at a time on a spawned tokio task, so this
+    /// must be called from within a runtime. Plain publishes (without a
+    /// reply topic) matching the filter are ignored.
+    pub fn serve<F, Fut>(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        handler: F,
+    ) -> Result<Responder, BusError>
+    where
+        F: Fn(Envelope) -> Fut + Send + 'static,
+        Fut: Future<Output = String> + Send + 'static,
+    {
+        let mut requests = self.subscribe_with(
+            filter,
+            DeliveryMode::Queue {
+                capacity: RESPONDER_QUEUE,
+                overflow: Overflow::Error,
+            },
+        )?;
+        let bus = self.clone();
+
+        let task = tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                if request.reply_to.is_none() {
+                    continue;
+                }
+                let reply = handler(request.clone()).await;
+                let _ = bus.reply(&request, reply);
+            }
+        });
+
+        Ok(Responder { task })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn request_reply_roundtrip() {
+        let bus = Bus::new();
+        let _responder = bus
+            .serve("history/last", |request| async move {
+                format!("games:{}", request.payload)
+            })
+            .unwrap();
+
+        let reply = bus
+            .request("history/last", "10", Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(reply.payload, "games:10");
+        assert!(reply.topic.starts_with(REPLY_PREFIX));
+    }
+
+    #[tokio::test]
+    async fn reply_topics_are_not_tracked() {
+        let bus = Bus::new();
+        let _responder = bus
+            .serve("echo", |request| async move { request.payload.to_string() })
+            .unwrap();
+
+        let mut reply_topics = Vec::new();
+        for _ in 0..3 {
+            let reply = bus
+                .request("echo", "x", Duration::from_secs(1))
+                .await
+                .unwrap();
+            reply_topics.push(reply.topic);
+        }
+        reply_topics.dedup();
+        assert_eq!(reply_topics.len(), 3);
+        assert!(
+            reply_topics
+                .iter()
+                .all(|topic| topic.len() > "$reply/0-".len() + 15)
+        );
+
+        let stats = bus.stats();
+        assert_eq!(stats.publishes, 6);
+        let topics: Vec<&str> = stats.topics.iter().map(|t| &*t.topic).collect();
+        assert_eq!(topics, ["echo"]);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_correlated() {
+        let bus = Bus::new();
+        let _responder = bus
+            .serve("echo", |request| async move { request.payload.to_string() })
+            .unwrap();
+
+        let (a, b) = tokio::join!(
+            bus.request("echo", "a", Duration::from_secs(1)),
+            bus.request("echo", "b", Duration::from_secs(1)),
+        );
+        assert_eq!(a.unwrap().payload, "a");
+        assert_eq!(b.unwrap().payload, "b");
+    }
+
+    #[tokio::test]
+    async fn request_without_responder() {
+        let bus = Bus::new();
+        let result = bus.request("nobody", "?", Duration::from_secs(1)).await;
+        assert!(matches!(result, Err(BusError::NoResponder)));
+    }
+
+    #[tokio::test]
+    async fn request_times_out() {
+        let bus = Bus::new();
+        let _silent = bus.subscribe("slow").unwrap();
+
+        let result = bus.request("slow", "?", Duration::from_millis(10)).await;
+        assert!(matches!(result, Err(BusError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn dropping_responder_stops_serving() {
+        let bus = Bus::new();
+        let responder = bus.serve("svc", |_| async { String::from("ok") }).unwrap();
+        drop(responder);
+        tokio::task::yield_now().await;
+
+        let result = bus.request("svc", "?", Duration::from_millis(10)).await;
+        assert!(matches!(
+            result,
+            Err(BusError::NoResponder | BusError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn reply_requires_request() {
+        let bus = Bus::new();
+        let envelope = Envelope::new("plain".into(), "x");
+        assert!(matches!(
+            bus.reply(&envelope, "y"),
+            Err(BusError::NotARequest)
+        ));
+    }
+}
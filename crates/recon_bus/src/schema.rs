@@ -0,0 +1,322 @@
+//! JSON Schema validation of payloads, keyed by topic filter.
+//!
+//! Once a schema is registered for a filter, every publish to a matching
+//! topic must carry a JSON payload that conforms to it. When several
+//! registered filters match a topic, the payload must satisfy all of them.
+
+use std::{fmt, path::Path, sync::Arc};
+
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{Bus, BusError, Payload, Topic, TopicError, topic_matches};
+
+struct Schema {
+    filter: Topic,
+    source: Value,
+    validator: jsonschema::Validator,
+}
+
+/// Registered schemas. Reads are lock-free so publishing never waits on a
+/// registration.
+#[derive(Default)]
+pub(crate) struct SchemaRegistry {
+    schemas: ArcSwap<Vec<Arc<Schema>>>,
+}
+
+impl SchemaRegistry {
+    fn insert(&self, schema: Schema) {
+        let schema = Arc::new(schema);
+        self.schemas.rcu(|schemas| {
+            let mut schemas: Vec<_> = schemas
+                .iter()
+                .filter(|s| s.filter != schema.filter)
+                .cloned()
+                .collect();
+            schemas.push(Arc::clone(&schema));
+            schemas
+        });
+    }
+
+    fn remove(&self, filter: &Topic) -> bool {
+        let previous = self.schemas.rcu(|schemas| {
+            schemas
+                .iter()
+                .filter(|s| s.filter != *filter)
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        previous.iter().any(|s| s.filter == *filter)
+    }
+
+    /// Check `payload` against every schema whose filter matches `topic`.
+    pub fn validate(&self, topic: &Topic, payload: &Payload) -> Result<(), ValidationError> {
+        let schemas = self.schemas.load();
+        let mut matching = schemas
+            .iter()
+            .filter(|s| topic_matches(&s.filter, topic))
+            .peekable();
+        let Some(first) = matching.peek() else {
+            return Ok(());
+        };
+
+        let error = |schema: &Schema, message: String| ValidationError {
+            topic: topic.as_raw().clone(),
+            filter: schema.filter.as_raw().clone(),
+            message,
+        };
+        let value: Value = serde_json::from_slice(payload.as_bytes())
+            .map_err(|e| error(first, format!("payload is not JSON: {e}")))?;
+        matching.try_for_each(|schema| {
+            schema
+                .validator
+                .validate(&value)
+                .map_err(|e| error(schema, e.to_string()))
+        })
+    }
+}
+
+/// A payload did not conform to a registered schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub topic: Arc<str>,
+    /// Filter the violated schema is registered under.
+    pub filter: Arc<str>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "payload for '{}' violates the schema for '{}': {}",
+            self.topic, self.filter, self.message
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A schema could not be loaded or compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub filter: Arc<str>,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schema for '{}': {}", self.filter, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Every registered schema and every topic published so far, from
+/// [`Bus::catalog`]. Serializes to JSON for plugin authors.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaCatalog {
+    /// Registered schemas, sorted by filter.
+    pub schemas: Vec<CatalogSchema>,
+    /// Published topics, sorted, with the filters of the schemas that
+    /// apply to them.
+    pub topics: Vec<CatalogTopic>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogSchema {
+    pub filter: String,
+    pub schema: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogTopic {
+    pub topic: String,
+    pub schemas: Vec<String>,
+}
+
+impl Bus {
+    /// Require payloads published to topics matching `filter` to conform to
+    /// the JSON Schema `schema`. Replaces any schema already registered for
+    /// the same filter.
+    pub fn register_schema(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        schema: Value,
+    ) -> Result<(), BusError> {
+        let filter = filter.try_into()?;
+        let validator = jsonschema::validator_for(&schema).map_err(|e| SchemaError {
+            filter: filter.as_raw().clone(),
+            message: e.to_string(),
+        })?;
+        self.inner.schemas.insert(Schema {
+            filter,
+            source: schema,
+            validator,
+        });
+        Ok(())
+    }
+
+    /// [`Bus::register_schema`] with a schema read from a JSON file.
+    pub fn register_schema_file(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+        path: impl AsRef<Path>,
+    ) -> Result<(), BusError> {
+        let filter = filter.try_into()?;
+        let path = path.as_ref();
+        let schema = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+            .map_err(|message| SchemaError {
+                filter: filter.as_raw().clone(),
+                message: format!("{}: {message}", path.display()),
+            })?;
+        self.register_schema(&filter, schema)
+    }
+
+    /// Stop validating topics matching `filter`. Returns whether a schema
+    /// was registered for exactly this filter.
+    pub fn unregister_schema(
+        &self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<bool, BusError> {
+        Ok(self.inner.schemas.remove(&filter.try_into()?))
+    }
+
+    /// Export every registered schema and every known topic.
+    pub fn catalog(&self) -> SchemaCatalog {
+        let registered = self.inner.schemas.schemas.load();
+
+        let mut schemas: Vec<CatalogSchema> = registered
+            .iter()
+            .map(|s| CatalogSchema {
+                filter: s.filter.to_string(),
+                schema: s.source.clone(),
+            })
+            .collect();
+        schemas.sort_by(|a, b| a.filter.cmp(&b.filter));
+
+        let mut topics: Vec<CatalogTopic> = self
+            .inner
+            .counters
+            .topic_names()
+            .into_iter()
+            .filter_map(|topic| Topic::try_from(&*topic).ok())
+            .map(|topic| {
+                let mut filters: Vec<String> = registered
+                    .iter()
+                    .filter(|s| topic_matches(&s.filter, &topic))
+                    .map(|s| s.filter.to_string())
+                    .collect();
+                filters.sort();
+                CatalogTopic {
+                    topic: topic.to_string(),
+                    schemas: filters,
+                }
+            })
+            .collect();
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+
+        SchemaCatalog { schemas, topics }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn status_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "state": { "enum": ["online", "offline"] } },
+            "required": ["state"]
+        })
+    }
+
+    #[tokio::test]
+    async fn rejects_nonconforming_payloads() {
+        let bus = Bus::new();
+        bus.register_schema("game/*/status", status_schema())
+            .unwrap();
+        let mut sub = bus.subscribe("game/*/status").unwrap();
+
+        let err = bus
+            .publish("game/apex/status", r#"{"state":"asleep"}"#)
+            .unwrap_err();
+        let BusError::Validation(err) = err else {
+            panic!("expected a validation error, got {err:?}");
+        };
+        assert_eq!(&*err.filter, "game/*/status");
+        assert!(bus.publish("game/apex/status", "online").is_err());
+        assert!(
+            bus.publish_bytes("game/apex/status", b"\xff".to_vec())
+                .is_err()
+        );
+
+        bus.publish("game/apex/status", r#"{"state":"online"}"#)
+            .unwrap();
+        assert_eq!(sub.recv().await.unwrap().sequence, 1);
+
+        // Topics outside the filter are not validated.
+        bus.publish("game/apex/health", "100").unwrap();
+    }
+
+    #[test]
+    fn every_matching_schema_applies() {
+        let bus = Bus::new();
+        bus.register_schema("game/**", json!({ "type": "object" }))
+            .unwrap();
+        bus.register_schema("game/*/status", status_schema())
+            .unwrap();
+
+        assert!(bus.publish("game/apex/health", "100").is_err());
+        assert!(bus.publish("game/apex/status", "{}").is_err());
+        assert!(
+            bus.publish("game/apex/status", r#"{"state":"offline"}"#)
+                .is_ok()
+        );
+
+        assert!(bus.unregister_schema("game/**").unwrap());
+        assert!(!bus.unregister_schema("game/**").unwrap());
+        assert!(bus.publish("game/apex/health", "100").is_ok());
+    }
+
+    #[test]
+    fn invalid_schema_is_rejected() {
+        let bus = Bus::new();
+        let err = bus
+            .register_schema("a", json!({ "type": "nonsense" }))
+            .unwrap_err();
+        assert!(matches!(err, BusError::Schema(_)));
+
+        let err = bus
+            .register_schema_file("a", "/nonexistent/status.schema.json")
+            .unwrap_err();
+        assert!(matches!(err, BusError::Schema(_)));
+    }
+
+    #[test]
+    fn catalog_lists_schemas_and_topics() {
+        let bus = Bus::new();
+        bus.register_schema("game/*/status", status_schema())
+            .unwrap();
+        bus.publish("game/apex/status", r#"{"state":"online"}"#)
+            .unwrap();
+        bus.publish("chat/message", "hi").unwrap();
+
+        let catalog = bus.catalog();
+        assert_eq!(catalog.schemas.len(), 1);
+        assert_eq!(catalog.schemas[0].schema, status_schema());
+        assert_eq!(catalog.topics.len(), 2);
+        assert_eq!(catalog.topics[0].topic, "chat/message");
+        assert!(catalog.topics[0].schemas.is_empty());
+        assert_eq!(catalog.topics[1].schemas, ["game/*/status"]);
+
+        let json = serde_json::to_value(&catalog).unwrap();
+        assert_eq!(json["topics"][1]["topic"], "game/apex/status");
+    }
+}
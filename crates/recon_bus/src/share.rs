@@ -0,0 +1,221 @@
+//! Shared subscriptions: a group of subscribers that split the messages
+//! matching a filter between them, in the style of MQTT's
+//! `$share/<group>/<filter>`.
+//!
+//! Each message goes to exactly one member. If the chosen member's queue
+//! refuses it, the next candidate is tried. When a member unsubscribes,
+//! whatever was still queued for it is handed to the remaining members.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    delivery::Push,
+    topic::{Topic, TopicError},
+    trie::SubscriberId,
+};
+
+/// First segment of a shared subscription filter.
+pub const SHARE_PREFIX: &str = "$share";
+
+/// How a shared group picks the member that gets the next message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShareStrategy {
+    /// Take turns in the order members joined.
+    #[default]
+    RoundRobin,
+    /// Pick the member that was handed a message longest ago. Members that
+    /// just joined go first.
+    LeastRecentlyDelivered,
+}
+
+/// Whether `filter` starts with `$share`.
+pub(crate) fn is_shared(filter: &Topic) -> bool {
+    filter.segment(0) == SHARE_PREFIX
+}
+
+/// Split `$share/<group>/<filter>` into the group key and the filter.
+///
+/// The key is `<group>/<filter>`, so the same group name on different
+/// filters forms separate groups. `None` for an ordinary filter.
+pub(crate) fn split(filter: &Topic) -> Result<Option<(Arc<str>, Topic)>, TopicError> {
+    if !is_shared(filter) {
+        return Ok(None);
+    }
+    let (_, key) = filter
+        .as_str()
+        .split_once('/')
+        .ok_or(TopicError::InvalidShare)?;
+    let (group, inner) = key.split_once('/').ok_or(TopicError::InvalidShare)?;
+    if group.contains('*') {
+        return Err(TopicError::InvalidShare);
+    }
+    Ok(Some((Arc::from(key), Topic::try_from(inner)?)))
+}
+
+#[derive(Debug)]
+struct Member {
+    id: SubscriberId,
+    /// Value of `GroupState::deliveries` when this member last took a
+    /// message.
+    last_delivery: u64,
+}
+
+#[derive(Debug, Default)]
+struct GroupState {
+    members: Vec<Member>,
+    /// Index of the next round-robin candidate.
+    next: usize,
+    deliveries: u64,
+}
+
+/// Members of one shared subscription and whose turn it is.
+#[derive(Debug)]
+pub(crate) struct ShareGroup {
+    key: Arc<str>,
+    strategy: ShareStrategy,
+    state: Mutex<GroupState>,
+}
+
+impl ShareGroup {
+    /// An empty group. The first member's strategy sticks for its lifetime.
+    pub fn new(key: Arc<str>, strategy: ShareStrategy) -> Self {
+        Self {
+            key,
+            strategy,
+            state: Mutex::new(GroupState::default()),
+        }
+    }
+
+    pub fn key(&self) -> &Arc<str> {
+        &self.key
+    }
+
+    pub fn join(&self, id: SubscriberId) {
+        self.lock().members.push(Member {
+            id,
+            last_delivery: 0,
+        });
+    }
+
+    /// Remove a member, returning whether the group is now empty.
+    pub fn leave(&self, id: SubscriberId) -> bool {
+        let mut state = self.lock();
+        if let Some(index) = state.members.iter().position(|m| m.id == id) {
+            state.members.remove(index);
+            if state.next > index {
+                state.next -= 1;
+            }
+        }
+        state.members.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().members.is_empty()
+    }
+
+    /// Offer a message to members in strategy order until one accepts it.
+    ///
+    /// `offer` returns `None` for members that are gone or do not want the
+    /// message. The result is the taking member's outcome, otherwise the
+    /// worst refusal, or `None` if no member was offered it at all.
+    pub fn deliver(&self, mut offer: impl FnMut(SubscriberId) -> Option<Push>) -> Option<Push> {
+        let mut state = self.lock();
+        let count = state.members.len();
+        let mut order: Vec<usize> = (0..count).map(|i| (state.next + i) % count).collect();
+        if self.strategy == ShareStrategy::LeastRecentlyDelivered {
+            order.sort_by_key(|&i| state.members[i].last_delivery);
+        }
+
+        let mut outcome = None;
+        for index in order {
+            match offer(state.members[index].id) {
+                Some(push) if push.accepted() => {
+                    state.deliveries += 1;
+                    state.members[index].last_delivery = state.deliveries;
+                    state.next = (index + 1) % count;
+                    return Some(push);
+                }
+                Some(Push::Rejected) => outcome = Some(Push::Rejected),
+                Some(Push::Dropped) if outcome.is_none() => outcome = Some(Push::Dropped),
+                Some(_) | None => {}
+            }
+        }
+        outcome
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GroupState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(strategy: ShareStrategy, members: u64) -> ShareGroup {
+        let group = ShareGroup::new(Arc::from("g/jobs"), strategy);
+        (0..members).for_each(|id| group.join(SubscriberId(id)));
+        group
+    }
+
+    /// Deliver one message to whichever member is picked first.
+    fn pick(group: &ShareGroup) -> u64 {
+        let mut picked = None;
+        group.deliver(|id| {
+            picked = Some(id.0);
+            Some(Push::Accepted)
+        });
+        picked.unwrap()
+    }
+
+    #[test]
+    fn split_share_filters() {
+        let t = |s: &str| Topic::try_from(s).unwrap();
+        let (key, filter) = split(&t("$share/workers/jobs/**")).unwrap().unwrap();
+        assert_eq!(&*key, "workers/jobs/**");
+        assert_eq!(filter, t("jobs/**"));
+        assert!(split(&t("jobs/**")).unwrap().is_none());
+        assert_eq!(split(&t("$share/workers")), Err(TopicError::InvalidShare));
+        assert_eq!(split(&t("$share/*/jobs")), Err(TopicError::InvalidShare));
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let group = group(ShareStrategy::RoundRobin, 3);
+        let picks: Vec<u64> = (0..6).map(|_| pick(&group)).collect();
+        assert_eq!(picks, [0, 1, 2, 0, 1, 2]);
+
+        group.leave(SubscriberId(1));
+        let picks: Vec<u64> = (0..3).map(|_| pick(&group)).collect();
+        assert_eq!(picks, [0, 2, 0]);
+    }
+
+    #[test]
+    fn least_recently_delivered_favours_newcomers() {
+        let group = group(ShareStrategy::LeastRecentlyDelivered, 2);
+        assert_eq!(pick(&group), 0);
+        assert_eq!(pick(&group), 1);
+        group.join(SubscriberId(2));
+        assert_eq!(pick(&group), 2);
+        assert_eq!(pick(&group), 0);
+    }
+
+    #[test]
+    fn full_members_are_skipped() {
+        let group = group(ShareStrategy::RoundRobin, 2);
+        let outcome = group.deliver(|id| {
+            Some(if id.0 == 0 {
+                Push::Rejected
+            } else {
+                Push::Accepted
+            })
+        });
+        assert_eq!(outcome, Some(Push::Accepted));
+
+        let outcome = group.deliver(|_| Some(Push::Rejected));
+        assert_eq!(outcome, Some(Push::Rejected));
+        assert!(!group.leave(SubscriberId(0)));
+        assert!(group.leave(SubscriberId(1)));
+        assert_eq!(group.deliver(|_| Some(Push::Accepted)), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! Publish counters and introspection snapshots.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use dashmap::{DashMap, mapref::one::Ref};
+
+use crate::{Bus, rpc};
+
+#[derive(Debug)]
+struct TopicCounters {
+    publishes: AtomicU64,
+    deliveries: AtomicU64,
+    unmatched: AtomicU64,
+    first_publish: Instant,
+    /// Nanoseconds after `first_publish`.
+    last_publish: AtomicU64,
+}
+
+impl TopicCounters {
+    fn new(now: Instant) -> Self {
+        Self {
+            publishes: AtomicU64::new(0),
+            deliveries: AtomicU64::new(0),
+            unmatched: AtomicU64::new(0),
+            first_publish: now,
+            last_publish: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Counters updated on every publish. All updates are relaxed atomics.
+#[derive(Debug, Default)]
+pub(crate) struct BusCounters {
+    publishes: AtomicU64,
+    deliveries: AtomicU64,
+    unmatched: AtomicU64,
+    dropped: AtomicU64,
+    topics: DashMap<Arc<str>, TopicCounters>,
+}
+
+impl BusCounters {
+    /// Count a publish to `topic` and return its per-topic sequence number,
+    /// starting at 1.
+    pub fn next_sequence(&self, topic: &Arc<str>) -> u64 {
+        self.publishes.fetch_add(1, Ordering::Relaxed);
+        // Each reply topic is used once, so counting it would only leave a
+        // dead entry behind per request.
+        if rpc::is_reply_topic(topic) {
+            return 1;
+        }
+
+        let now = Instant::now();
+        let counters = self.topic(topic, now);
+        let since = now.saturating_duration_since(counters.first_publish);
+        counters
+            .last_publish
+            .fetch_max(since.as_nanos() as u64, Ordering::Relaxed);
+        counters.publishes.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Record the outcome of delivering one publish to `topic`: how many
+    /// subscribers it was offered to, and how many accepted or dropped it.
+    pub fn record(&self, topic: &Arc<str>, matched: usize, delivered: usize, dropped: usize) {
+        let delivered = delivered as u64;
+        let unmatched = u64::from(matched == 0);
+
+        self.deliveries.fetch_add(delivered, Ordering::Relaxed);
+        self.unmatched.fetch_add(unmatched, Ordering::Relaxed);
+        self.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+        if rpc::is_reply_topic(topic) {
+            return;
+        }
+
+        let counters = self.topic(topic, Instant::now());
+        counters.deliveries.fetch_add(delivered, Ordering::Relaxed);
+        counters.unmatched.fetch_add(unmatched, Ordering::Relaxed);
+    }
+
+    /// Every topic published to so far.
+    #[cfg(feature = "schema")]
+    pub fn topic_names(&self) -> Vec<Arc<str>> {
+        self.topics.iter().map(|e| Arc::clone(e.key())).collect()
+    }
+
+    fn topic(&self, topic: &Arc<str>, now: Instant) -> Ref<'_, Arc<str>, TopicCounters> {
+        self.topics.get(topic).unwrap_or_else(|| {
+            self.topics
+                .entry(Arc::clone(topic))
+                .or_insert_with(|| TopicCounters::new(now))
+                .downgrade()
+        })
+    }
+}
+
+/// Snapshot of bus-wide counters, from [`Bus::stats`].
+#[derive(Debug, Clone)]
+pub struct BusStats {
+    /// Total publish calls that reached delivery.
+    pub publishes: u64,
+    /// Total envelopes accepted by subscribers.
+    pub deliveries: u64,
+    /// Publishes that matched no subscriber.
+    pub unmatched: u64,
+    /// Envelopes discarded or rejected by full subscriber queues, including
+    /// queued ones evicted by [`Overflow::DropOldest`](crate::Overflow).
+    pub dropped: u64,
+    /// Live subscriptions.
+    pub subscribers: usize,
+    /// Nodes in use by the routing trie.
+    pub trie_nodes: usize,
+    /// Per-topic counters, busiest first.
+    pub topics: Vec<TopicStats>,
+}
+
+/// Counters for one concrete topic.
+#[derive(Debug, Clone)]
+pub struct TopicStats {
+    pub topic: Arc<str>,
+    pub publishes: u64,
+    pub deliveries: u64,
+    pub unmatched: u64,
+    /// Average publishes per second since the first publish.
+    pub rate: f64,
+    pub first_publish: Instant,
+    pub last_publish: Instant,
+}
+
+/// A registered subscription filter, from [`Bus::topics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterStats {
+    pub filter: String,
+    pub subscribers: usize,
+}
+
+impl Bus {
+    /// Snapshot publish counters.
+    pub fn stats(&self) -> BusStats {
+        let counters = &self.inner.counters;
+        let mut topics: Vec<TopicStats> = counters
+            .topics
+            .iter()
+            .map(|entry| {
+                let c = entry.value();
+                let publishes = c.publishes.load(Ordering::Relaxed);
+                let elapsed = c.first_publish.elapsed().as_secs_f64();
+                TopicStats {
+                    topic: Arc::clone(entry.key()),
+                    publishes,
+                    deliveries: c.deliveries.load(Ordering::Relaxed),
+                    unmatched: c.unmatched.load(Ordering::Relaxed),
+                    rate: if elapsed > 0.0 {
+                        publishes as f64 / elapsed
+                    } else {
+                        0.0
+                    },
+                    first_publish: c.first_publish,
+                    last_publish: c.first_publish
+                        + Duration::from_nanos(c.last_publish.load(Ordering::Relaxed)),
+                }
+            })
+            .collect();
+        topics.sort_by(|a, b| {
+            b.publishes
+                .cmp(&a.publishes)
+                .then_with(|| a.topic.cmp(&b.topic))
+        });
+
+        BusStats {
+            publishes: counters.publishes.load(Ordering::Relaxed),
+            deliveries: counters.deliveries.load(Ordering::Relaxed),
+            unmatched: counters.unmatched.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            subscribers: self.inner.subscribers.len(),
+            trie_nodes: self.inner.routes.load().node_count(),
+            topics,
+        }
+    }
+
+    /// Snapshot every registered filter and its subscriber count, sorted by
+    /// filter.
+    pub fn topics(&self) -> Vec<FilterStats> {
+        self.inner
+            .routes
+            .load()
+            .filters()
+            .into_iter()
+            .map(|(filter, subscribers)| FilterStats {
+                filter,
+                subscribers,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Bus, DeliveryMode, Overflow};
+
+    #[test]
+    fn counts_publishes_and_deliveries() {
+        let bus = Bus::new();
+        let _a = bus.subscribe("game/*/status").unwrap();
+        let _b = bus.subscribe("game/**").unwrap();
+
+        bus.publish("game/apex/status", "up").unwrap();
+        bus.publish("game/apex/status", "down").unwrap();
+        bus.publish("game/apex/health", "100").unwrap();
+        bus.publish("nobody/listens", "?").unwrap();
+
+        let stats = bus.stats();
+        assert_eq!(stats.publishes, 4);
+        assert_eq!(stats.deliveries, 5);
+        assert_eq!(stats.unmatched, 1);
+        assert_eq!(stats.subscribers, 2);
+
+        let busiest = &stats.topics[0];
+        assert_eq!(&*busiest.topic, "game/apex/status");
+        assert_eq!(busiest.publishes, 2);
+        assert_eq!(busiest.deliveries, 4);
+        assert!(busiest.last_publish >= busiest.first_publish);
+
+        let unmatched = stats
+            .topics
+            .iter()
+            .find(|t| &*t.topic == "nobody/listens")
+            .unwrap();
+        assert_eq!(unmatched.unmatched, 1);
+    }
+
+    #[test]
+    fn counts_dropped() {
+        let bus = Bus::new();
+        let dropping = bus
+            .subscribe_with(
+                "feed",
+                DeliveryMode::Queue {
+                    capacity: 1,
+                    overflow: Overflow::DropNewest,
+                },
+            )
+            .unwrap();
+
+        bus.publish("feed", "1").unwrap();
+        bus.publish("feed", "2").unwrap();
+
+        let stats = bus.stats();
+        // The subscriber matched, even though it dropped the second publish.
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.unmatched, 0);
+
+        // Evicting the oldest envelope delivers the new one but still loses one.
+        drop(dropping);
+        let _evicting = bus.subscribe_with("feed", DeliveryMode::queue(1)).unwrap();
+        assert_eq!(bus.publish("feed", "3").unwrap(), 1);
+        assert_eq!(bus.publish("feed", "4").unwrap(), 1);
+
+        let stats = bus.stats();
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.deliveries, 3);
+    }
+
+    #[test]
+    fn lists_filters() {
+        let bus = Bus::new();
+        let _a = bus.subscribe("game/*/status").unwrap();
+        let _b = bus.subscribe("game/*/status").unwrap();
+        let c = bus.subscribe("game/**").unwrap();
+
+        let topics = bus.topics();
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[0].filter, "game/**");
+        assert_eq!(topics[0].subscribers, 1);
+        assert_eq!(topics[1].filter, "game/*/status");
+        assert_eq!(topics[1].subscribers, 2);
+
+        drop(c);
+        assert_eq!(bus.topics().len(), 1);
+        assert_eq!(bus.stats().trie_nodes, 4);
+    }
+}
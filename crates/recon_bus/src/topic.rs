@@ -1,12 +1,21 @@
 //! Topic parsing, validation, and wildcard matching.
 
-use std::{fmt, sync::Arc};
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use crate::rpc::REPLY_PREFIX;
+
+/// First segment of the reserved namespace only the host publishes to.
+pub const SYSTEM_PREFIX: &str = "$sys";
 
 /// A topic path with `/`-separated segments.
 ///
 /// Supports wildcards for subscription filters:
 /// - `*` matches exactly one segment
-/// - `**` matches zero or more remaining segments (must be last)
+/// - `**` matches zero or more segments, in any position
+/// - a segment glob such as `player-*` matches one segment, with each `*`
+///   standing for any run of characters within it
+///
+/// [`Topic::parse_mqtt`] accepts MQTT filter syntax instead.
 ///
 /// Separator indices are cached at construction so segment access never allocates.
 #[derive(Debug, Clone)]
@@ -53,6 +62,52 @@ impl Topic {
     pub fn has_wildcards(&self) -> bool {
         self.raw.contains('*')
     }
+
+    /// Whether this topic is in the reserved `$sys` namespace.
+    pub fn is_system(&self) -> bool {
+        self.segment(0) == SYSTEM_PREFIX
+    }
+
+    /// Whether this topic is in the `$reply` namespace of request replies.
+    pub fn is_reply(&self) -> bool {
+        self.segment(0) == REPLY_PREFIX
+    }
+
+    /// Parse a topic a plugin or external client wants to publish to.
+    ///
+    /// Rejects the reserved `$sys` namespace, which only the host publishes
+    /// to through [`Bus`](crate::Bus) directly, and `$reply`, which is only
+    /// published to through [`Bus::reply`](crate::Bus::reply).
+    pub fn parse_external(s: &str) -> Result<Self, TopicError> {
+        let topic = Self::try_from(s)?;
+        if topic.is_system() || topic.is_reply() {
+            return Err(TopicError::Reserved);
+        }
+        Ok(topic)
+    }
+
+    /// Parse an MQTT topic or filter, translating `+` to `*` and `#` to
+    /// `**`. As in MQTT, `#` must be the last segment and wildcards must
+    /// fill their segment. `*` has no literal form here and is rejected.
+    pub fn parse_mqtt(s: &str) -> Result<Self, TopicError> {
+        if s.is_empty() {
+            return Err(TopicError::Empty);
+        }
+        let count = s.split('/').count();
+        let segments: Vec<&str> = s
+            .split('/')
+            .enumerate()
+            .map(|(i, seg)| match seg {
+                "+" => Ok("*"),
+                "#" if i == count - 1 => Ok("**"),
+                "#" => Err(TopicError::MultiWildcardNotLast),
+                _ if seg.contains(['+', '#']) => Err(TopicError::WildcardMixedWithText),
+                _ if seg.contains('*') => Err(TopicError::InvalidCharacter('*')),
+                _ => Ok(seg),
+            })
+            .collect::<Result<_, _>>()?;
+        Self::try_from(segments.join("/"))
+    }
 }
 
 impl PartialEq for Topic {
@@ -75,6 +130,14 @@ pub enum TopicError {
     EmptySegment,
     WildcardMixedWithText,
     MultiWildcardNotLast,
+    InvalidCharacter(char),
+    /// Only the host may publish to `$sys` and `$reply` topics.
+    Reserved,
+    /// A `$share` filter without a plain group name and a filter.
+    InvalidShare,
+    /// A filter with no MQTT form, such as one with a segment glob or a
+    /// `**` before the end.
+    NoMqttEquivalent,
 }
 
 impl fmt::Display for TopicError {
@@ -82,10 +145,19 @@ impl fmt::Display for TopicError {
         match self {
             Self::Empty => write!(f, "topic must not be empty"),
             Self::EmptySegment => write!(f, "topic must not contain empty segments"),
-            Self::MultiWildcardNotLast => write!(f, "'**' must be the last segment"),
+            Self::MultiWildcardNotLast => write!(f, "'#' must be the last segment"),
             Self::WildcardMixedWithText => {
-                write!(f, "'*' and '**' must be the entire segment")
+                write!(f, "'**', '+' and '#' must be the entire segment")
             }
+            Self::InvalidCharacter(c) => write!(f, "'{c}' is not allowed here"),
+            Self::Reserved => write!(
+                f,
+                "'{SYSTEM_PREFIX}' and '{REPLY_PREFIX}' topics are reserved for the host"
+            ),
+            Self::InvalidShare => {
+                write!(f, "shared filters must look like '$share/<group>/<filter>'")
+            }
+            Self::NoMqttEquivalent => write!(f, "filter has no MQTT equivalent"),
         }
     }
 }
@@ -114,18 +186,23 @@ impl TryFrom<&str> for Topic {
             if seg.is_empty() {
                 return Err(TopicError::EmptySegment);
             }
-            if seg == "*" || seg == "**" {
-                if seg == "**" && i != count - 1 {
-                    return Err(TopicError::MultiWildcardNotLast);
-                }
-                return Ok(());
-            }
-            if seg.contains('*') {
+            if seg != "**" && seg.contains("**") {
                 return Err(TopicError::WildcardMixedWithText);
             }
             Ok(())
         })?;
 
+        // `**/**` matches exactly what `**` does. Collapsing runs keeps the
+        // number of ways a filter can match a topic down.
+        if s.split('/')
+            .zip(s.split('/').skip(1))
+            .any(|pair| pair == ("**", "**"))
+        {
+            let mut segments: Vec<&str> = s.split('/').collect();
+            segments.dedup_by(|a, b| *a == "**" && *b == "**");
+            return Self::try_from(segments.join("/"));
+        }
+
         Ok(Self {
             raw: Arc::from(s),
             separators,
@@ -141,6 +218,14 @@ impl TryFrom<String> for Topic {
     }
 }
 
+impl TryFrom<&Topic> for Topic {
+    type Error = TopicError;
+
+    fn try_from(t: &Topic) -> Result<Self, Self::Error> {
+        Ok(t.clone())
+    }
+}
+
 impl fmt::Display for Topic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(&self.raw)
@@ -153,28 +238,184 @@ pub fn topic_matches(filter: &Topic, topic: &Topic) -> bool {
     let mut ti = 0;
     let fc = filter.segment_count();
     let tc = topic.segment_count();
-
-    while fi < fc {
-        match filter.segment(fi) {
-            "**" => return true,
-            "*" => {
-                if ti >= tc {
-                    return false;
-                }
+    // Where to resume after the most recent `**` if the rest fails to match:
+    // the filter segment after it, and the topic segment it would absorb
+    // next. Retrying only the latest `**` is enough, as in glob matching.
+    let mut retry: Option<(usize, usize)> = None;
+
+    while ti < tc {
+        if fi < fc {
+            let seg = filter.segment(fi);
+            if seg == "**" {
                 fi += 1;
-                ti += 1;
+                retry = Some((fi, ti));
+                continue;
             }
-            literal => {
-                if ti >= tc || topic.segment(ti) != literal {
-                    return false;
-                }
+            if segment_matches(seg, topic.segment(ti)) {
                 fi += 1;
                 ti += 1;
+                continue;
+            }
+        }
+        let Some((resume, absorbed)) = retry else {
+            return false;
+        };
+        fi = resume;
+        ti = absorbed + 1;
+        retry = Some((resume, ti));
+    }
+
+    (fi..fc).all(|i| filter.segment(i) == "**")
+}
+
+/// Check if a single filter segment (not `**`) matches a topic segment.
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    if pattern.contains('*') {
+        glob_matches(pattern, segment)
+    } else {
+        pattern == segment
+    }
+}
+
+/// Match a segment glob, where each `*` stands for any run of characters.
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let (middle, suffix) = rest.rsplit_once('*').unwrap_or(("", rest));
+    if text.len() < prefix.len() + suffix.len()
+        || !text.starts_with(prefix)
+        || !text.ends_with(suffix)
+    {
+        return false;
+    }
+    let mut text = &text[prefix.len()..text.len() - suffix.len()];
+    middle
+        .split('*')
+        .filter(|part| !part.is_empty())
+        .all(|part| match text.find(part) {
+            Some(at) => {
+                text = &text[at + part.len()..];
+                true
             }
+            None => false,
+        })
+}
+
+/// The literal text a glob must start and end with.
+fn glob_bounds(pattern: &str) -> (&str, &str) {
+    match (pattern.split_once('*'), pattern.rsplit_once('*')) {
+        (Some((prefix, _)), Some((_, suffix))) => (prefix, suffix),
+        _ => (pattern, pattern),
+    }
+}
+
+/// Check if every topic matched by `inner` is also matched by `outer`.
+///
+/// Conservative: may answer `false` for some glob pairs where every match
+/// is in fact covered, never `true` when one is not.
+pub(crate) fn filter_covers(outer: &Topic, inner: &Topic) -> bool {
+    let oc = outer.segment_count();
+    let ic = inner.segment_count();
+    search_pairs(|oi, ii, next| {
+        if oi == oc {
+            return ii == ic;
+        }
+        match outer.segment(oi) {
+            "**" => {
+                next.push((oi + 1, ii));
+                if ii < ic {
+                    next.push((oi, ii + 1));
+                }
+            }
+            seg if ii < ic && segment_covers(seg, inner.segment(ii)) => {
+                next.push((oi + 1, ii + 1));
+            }
+            _ => {}
+        }
+        false
+    })
+}
+
+/// Depth-first search over pairs of segment indices into two filters,
+/// starting at `(0, 0)`. `step` either accepts a pair or pushes the pairs
+/// it leads to.
+///
+/// Each pair is visited once, so the cost is bounded by the product of the
+/// two lengths however many `**` either filter has.
+fn search_pairs(mut step: impl FnMut(usize, usize, &mut Vec<(usize, usize)>) -> bool) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![(0, 0)];
+    while let Some((i, j)) = stack.pop() {
+        if visited.insert((i, j)) && step(i, j, &mut stack) {
+            return true;
         }
     }
+    false
+}
 
-    ti == tc
+/// Check if a single-segment filter token covers another token.
+fn segment_covers(outer: &str, inner: &str) -> bool {
+    if inner == "**" {
+        return false;
+    }
+    if outer == "*" || outer == inner {
+        return true;
+    }
+    if !inner.contains('*') {
+        return segment_matches(outer, inner);
+    }
+    // A glob with one `*` only constrains how a segment starts and ends.
+    if outer.matches('*').count() == 1 {
+        let (op, os) = glob_bounds(outer);
+        let (ip, is) = glob_bounds(inner);
+        return ip.starts_with(op) && is.ends_with(os);
+    }
+    false
+}
+
+/// Check if at least one topic could be matched by both filters.
+///
+/// Conservative: may answer `true` for some glob pairs that share no
+/// topic, never `false` when they do.
+pub(crate) fn filters_overlap(a: &Topic, b: &Topic) -> bool {
+    let ac = a.segment_count();
+    let bc = b.segment_count();
+    search_pairs(|ai, bi, next| {
+        let sa = (ai < ac).then(|| a.segment(ai));
+        let sb = (bi < bc).then(|| b.segment(bi));
+        match (sa, sb) {
+            (None, None) => return true,
+            (Some("**"), _) => {
+                next.push((ai + 1, bi));
+                if bi < bc {
+                    next.push((ai, bi + 1));
+                }
+            }
+            (_, Some("**")) => {
+                next.push((ai, bi + 1));
+                if ai < ac {
+                    next.push((ai + 1, bi));
+                }
+            }
+            (Some(x), Some(y)) if segments_overlap(x, y) => next.push((ai + 1, bi + 1)),
+            _ => {}
+        }
+        false
+    })
+}
+
+/// Check if some segment could match both single-segment tokens.
+fn segments_overlap(x: &str, y: &str) -> bool {
+    match (x.contains('*'), y.contains('*')) {
+        (false, false) => x == y,
+        (true, false) => glob_matches(x, y),
+        (false, true) => glob_matches(y, x),
+        (true, true) => {
+            let ((xp, xs), (yp, ys)) = (glob_bounds(x), glob_bounds(y));
+            (xp.starts_with(yp) || yp.starts_with(xp)) && (xs.ends_with(ys) || ys.ends_with(xs))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -191,6 +432,10 @@ mod tests {
         assert!(Topic::try_from("**").is_ok());
         assert!(Topic::try_from("*").is_ok());
         assert!(Topic::try_from("*/*/*").is_ok());
+        assert!(Topic::try_from("game/**/health").is_ok());
+        assert!(Topic::try_from("**/health/**").is_ok());
+        assert!(Topic::try_from("players/player-*/score").is_ok());
+        assert!(Topic::try_from("a/*b*c*").is_ok());
     }
 
     #[test]
@@ -198,15 +443,55 @@ mod tests {
         assert_eq!(Topic::try_from(""), Err(TopicError::Empty));
         assert_eq!(Topic::try_from("a//b"), Err(TopicError::EmptySegment));
         assert_eq!(
-            Topic::try_from("a/**/b"),
-            Err(TopicError::MultiWildcardNotLast)
+            Topic::try_from("a/b**c"),
+            Err(TopicError::WildcardMixedWithText)
         );
         assert_eq!(
-            Topic::try_from("a/b*c"),
+            Topic::try_from("a/***"),
             Err(TopicError::WildcardMixedWithText)
         );
     }
 
+    #[test]
+    fn repeated_double_stars_collapse() {
+        assert_eq!(t("**/**/a/**/**/**"), t("**/a/**"));
+        assert_eq!(t("a/**/**").segment_count(), 2);
+        assert!(topic_matches(&t("a/**/**/b"), &t("a/b")));
+    }
+
+    #[test]
+    fn system_namespace() {
+        assert!(t("$sys/window/opened").is_system());
+        assert!(!t("game/$sys").is_system());
+        assert!(!t("$system/x").is_system());
+        assert_eq!(
+            Topic::parse_external("$sys/plugins/a/loaded"),
+            Err(TopicError::Reserved)
+        );
+        assert_eq!(Topic::parse_external("$sys"), Err(TopicError::Reserved));
+        assert!(Topic::parse_external("game/apex/status").is_ok());
+        assert_eq!(
+            Topic::parse_external("$reply/1-2"),
+            Err(TopicError::Reserved)
+        );
+        assert!(t("$reply/1-2").is_reply());
+        assert!(!t("game/$reply").is_reply());
+        assert!(topic_matches(&t("$sys/**"), &t("$sys/window/opened")));
+    }
+
+    #[test]
+    fn mqtt_syntax() {
+        let parse = |s| Topic::parse_mqtt(s).map(|t| t.to_string());
+        assert_eq!(parse("game/+/status").as_deref(), Ok("game/*/status"));
+        assert_eq!(parse("game/#").as_deref(), Ok("game/**"));
+        assert_eq!(parse("#").as_deref(), Ok("**"));
+        assert_eq!(parse("$SYS/broker").as_deref(), Ok("$SYS/broker"));
+        assert_eq!(parse("a/#/b"), Err(TopicError::MultiWildcardNotLast));
+        assert_eq!(parse("a/b+"), Err(TopicError::WildcardMixedWithText));
+        assert_eq!(parse("a/b*"), Err(TopicError::InvalidCharacter('*')));
+        assert_eq!(parse("a//b"), Err(TopicError::EmptySegment));
+    }
+
     #[test]
     fn segment_indexing() {
         let t = Topic::try_from("game/valorant/status").unwrap();
@@ -290,10 +575,103 @@ mod tests {
         assert!(topic_matches(&f, &Topic::try_from("a/b/c").unwrap()));
     }
 
+    #[test]
+    fn double_star_in_the_middle() {
+        let f = t("game/**/health");
+        assert!(topic_matches(&f, &t("game/health")));
+        assert!(topic_matches(&f, &t("game/apex/health")));
+        assert!(topic_matches(&f, &t("game/apex/squad/2/health")));
+        assert!(!topic_matches(&f, &t("game/apex/health/max")));
+        assert!(!topic_matches(&f, &t("chat/health")));
+
+        // The match has to backtrack past an earlier `health`.
+        assert!(topic_matches(
+            &t("**/health/max"),
+            &t("health/x/health/max")
+        ));
+        assert!(topic_matches(&t("**/a/**/b"), &t("a/a/b/b")));
+        assert!(!topic_matches(&t("**/a/**/b"), &t("b/a")));
+    }
+
+    #[test]
+    fn segment_globs() {
+        let f = t("players/player-*/score");
+        assert!(topic_matches(&f, &t("players/player-1/score")));
+        assert!(topic_matches(&f, &t("players/player-/score")));
+        assert!(!topic_matches(&f, &t("players/bot-1/score")));
+        assert!(!topic_matches(&f, &t("players/player-1/2/score")));
+
+        assert!(glob_matches("*-bot", "aim-bot"));
+        assert!(glob_matches("a*b*c", "abc"));
+        assert!(glob_matches("a*b*c", "a-b-b-c"));
+        assert!(!glob_matches("a*b*c", "acb"));
+        assert!(!glob_matches("ab*ba", "aba"));
+    }
+
     #[test]
     fn length_mismatch() {
         let f = Topic::try_from("a/b").unwrap();
         assert!(!topic_matches(&f, &Topic::try_from("a/b/c").unwrap()));
         assert!(!topic_matches(&f, &Topic::try_from("a").unwrap()));
     }
+
+    fn t(s: &str) -> Topic {
+        Topic::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn covers() {
+        assert!(filter_covers(&t("**"), &t("game/**")));
+        assert!(filter_covers(&t("game/**"), &t("game/*/status")));
+        assert!(filter_covers(&t("game/*/status"), &t("game/*/status")));
+        assert!(filter_covers(&t("game/*/status"), &t("game/apex/status")));
+        assert!(!filter_covers(&t("game/*"), &t("game/**")));
+        assert!(!filter_covers(&t("game/apex/status"), &t("game/*/status")));
+        assert!(!filter_covers(&t("game/*/status"), &t("game/*")));
+
+        assert!(filter_covers(&t("game/**/health"), &t("game/*/health")));
+        assert!(filter_covers(&t("game/**/health"), &t("game/**/health")));
+        assert!(filter_covers(&t("**/health"), &t("game/**/health")));
+        assert!(!filter_covers(&t("game/**/health"), &t("game/**")));
+        assert!(!filter_covers(&t("game/*/health"), &t("game/**/health")));
+
+        assert!(filter_covers(&t("player-*"), &t("player-1")));
+        assert!(filter_covers(&t("player-*"), &t("player-1*")));
+        assert!(filter_covers(&t("*"), &t("player-*")));
+        assert!(!filter_covers(&t("player-*"), &t("*")));
+        assert!(!filter_covers(&t("player-*"), &t("play*")));
+    }
+
+    #[test]
+    fn overlap() {
+        assert!(filters_overlap(&t("game/**"), &t("game/*/debug/**")));
+        assert!(filters_overlap(&t("game/*/debug"), &t("game/apex/*")));
+        assert!(filters_overlap(&t("game/**"), &t("game")));
+        assert!(!filters_overlap(&t("game/*/debug"), &t("game/apex/status")));
+        assert!(!filters_overlap(&t("game/*"), &t("game/a/b")));
+
+        assert!(filters_overlap(&t("game/**/health"), &t("game/apex/*")));
+        assert!(filters_overlap(&t("**/debug"), &t("game/*/debug/**")));
+        assert!(!filters_overlap(
+            &t("game/**/health"),
+            &t("game/apex/status")
+        ));
+        assert!(!filters_overlap(&t("**/health"), &t("chat")));
+
+        assert!(filters_overlap(&t("player-*"), &t("*-1")));
+        assert!(filters_overlap(&t("player-*"), &t("player-1")));
+        assert!(!filters_overlap(&t("player-*"), &t("bot-*")));
+        assert!(!filters_overlap(&t("player-*"), &t("bot-1")));
+    }
+
+    #[test]
+    fn many_double_stars_compare_quickly() {
+        let filter = t(&format!("game/{}status", "**/x/".repeat(300)));
+        let deny = t("game/**/debug");
+        assert!(!filters_overlap(&deny, &filter));
+        assert!(!filters_overlap(&filter, &deny));
+        assert!(filter_covers(&t("game/**"), &filter));
+        assert!(!filter_covers(&filter, &deny));
+        assert!(!filter_covers(&filter, &t(&["x"; 600].join("/"))));
+    }
 }
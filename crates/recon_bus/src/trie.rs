@@ -1,118 +1,248 @@
 //! Arena-based topic trie for wildcard subscription matching.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{Index, IndexMut},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
-use crate::topic::Topic;
+use arc_swap::{ArcSwap, Guard};
+
+use crate::topic::{Topic, glob_matches};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct SubscriberId(pub u64);
 
 type NodeId = usize;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 struct TrieNode {
     subscribers: Vec<SubscriberId>,
     children: HashMap<Arc<str>, NodeId>,
     single_wildcard: Option<NodeId>,
+    /// Segment globs such as `player-*`, checked one by one.
+    globs: Vec<(Arc<str>, NodeId)>,
+    /// A `**` followed by more segments.
+    multi_wildcard: Option<NodeId>,
+    /// A trailing `**`.
     multi_wildcard_subscribers: Vec<SubscriberId>,
 }
 
+impl TrieNode {
+    fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+            && self.children.is_empty()
+            && self.single_wildcard.is_none()
+            && self.globs.is_empty()
+            && self.multi_wildcard.is_none()
+            && self.multi_wildcard_subscribers.is_empty()
+    }
+}
+
+/// Nodes per [`Arena`] chunk.
+const CHUNK: usize = 64;
+
+/// Node storage that is cheap to clone.
+///
+/// Nodes sit behind `Arc`s in fixed-size chunks, themselves behind `Arc`s.
+/// A clone copies one pointer per chunk, and writing to a node afterwards
+/// copies only that node and its chunk's pointers, so clones keep sharing
+/// everything that was not written to.
+#[derive(Debug, Clone, Default)]
+struct Arena {
+    chunks: Vec<Arc<Vec<Arc<TrieNode>>>>,
+    len: usize,
+}
+
+impl Arena {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, node: TrieNode) -> NodeId {
+        let id = self.len;
+        if id.is_multiple_of(CHUNK) {
+            self.chunks.push(Arc::new(Vec::with_capacity(CHUNK)));
+        }
+        let chunk = self.chunks.last_mut().expect("a chunk has room");
+        Arc::make_mut(chunk).push(Arc::new(node));
+        self.len += 1;
+        id
+    }
+}
+
+impl Index<NodeId> for Arena {
+    type Output = TrieNode;
+
+    fn index(&self, id: NodeId) -> &TrieNode {
+        &self.chunks[id / CHUNK][id % CHUNK]
+    }
+}
+
+impl IndexMut<NodeId> for Arena {
+    fn index_mut(&mut self, id: NodeId) -> &mut TrieNode {
+        let chunk = Arc::make_mut(&mut self.chunks[id / CHUNK]);
+        Arc::make_mut(&mut chunk[id % CHUNK])
+    }
+}
+
+/// How a node hangs off its parent.
+#[derive(Debug, Clone, Copy)]
+enum Link<'a> {
+    Literal(&'a str),
+    Glob(&'a str),
+    SingleWildcard,
+    MultiWildcard,
+}
+
+impl<'a> Link<'a> {
+    /// The link a non-trailing filter segment follows.
+    fn of(segment: &'a str) -> Self {
+        match segment {
+            "*" => Self::SingleWildcard,
+            "**" => Self::MultiWildcard,
+            glob if glob.contains('*') => Self::Glob(glob),
+            literal => Self::Literal(literal),
+        }
+    }
+}
+
 /// Arena-based topic trie for wildcard matching.
 ///
-/// Nodes are stored in a flat `Vec` indexed by `NodeId`. All operations
-/// are iterative.
-#[derive(Debug)]
+/// Nodes are stored in an [`Arena`] indexed by `NodeId`. All operations
+/// are iterative. Removing the last subscriber of a subtree prunes it, and
+/// freed slots are reused by later inserts, so the arena never grows past
+/// the peak number of live nodes.
+#[derive(Debug, Clone)]
 pub(crate) struct TopicTrie {
-    nodes: Vec<TrieNode>,
+    nodes: Arena,
+    free: Vec<NodeId>,
+    /// Subscribers routed by more than one filter. While there are any, a
+    /// subscriber may be found more than once.
+    multi_filter: usize,
 }
 
 impl TopicTrie {
     pub fn new() -> Self {
+        let mut nodes = Arena::default();
+        nodes.push(TrieNode::default());
         Self {
-            nodes: vec![TrieNode::default()],
+            nodes,
+            free: Vec::new(),
+            multi_filter: 0,
         }
     }
 
+    /// Number of nodes in use, including the root.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
     pub fn insert(&mut self, filter: &Topic, id: SubscriberId) {
         let mut current = 0;
         let count = filter.segment_count();
 
         for i in 0..count {
             let seg = filter.segment(i);
-            match seg {
-                "**" => {
-                    self.nodes[current].multi_wildcard_subscribers.push(id);
-                    return;
-                }
-                "*" => {
-                    let child = if let Some(child_id) = self.nodes[current].single_wildcard {
-                        child_id
-                    } else {
-                        let child_id = self.alloc_node();
-                        self.nodes[current].single_wildcard = Some(child_id);
-                        child_id
-                    };
-
-                    if i == count - 1 {
-                        self.nodes[child].subscribers.push(id);
-                        return;
-                    }
-                    current = child;
-                }
-                literal => {
-                    let key: Arc<str> = Arc::from(literal);
-                    let child = if let Some(&child_id) = self.nodes[current].children.get(&key) {
-                        child_id
-                    } else {
-                        let child_id = self.alloc_node();
-                        self.nodes[current].children.insert(key, child_id);
-                        child_id
-                    };
-
-                    if i == count - 1 {
-                        self.nodes[child].subscribers.push(id);
-                        return;
-                    }
-                    current = child;
-                }
+            if seg == "**" && i == count - 1 {
+                self.nodes[current].multi_wildcard_subscribers.push(id);
+                return;
             }
+            current = match self.child(current, Link::of(seg)) {
+                Some(child) => child,
+                None => {
+                    let child = self.alloc_node();
+                    self.link(current, Link::of(seg), child);
+                    child
+                }
+            };
         }
+        self.nodes[current].subscribers.push(id);
     }
 
     pub fn remove(&mut self, filter: &Topic, id: SubscriberId) {
         let mut current = 0;
         let count = filter.segment_count();
+        // Links walked from the root, so emptied nodes can be unlinked.
+        let mut path: Vec<(NodeId, Link<'_>)> = Vec::with_capacity(count);
 
         for i in 0..count {
             let seg = filter.segment(i);
-            match seg {
-                "**" => {
-                    self.nodes[current]
-                        .multi_wildcard_subscribers
-                        .retain(|s| *s != id);
-                    return;
-                }
-                "*" => {
-                    let Some(child) = self.nodes[current].single_wildcard else {
-                        return;
-                    };
-                    if i == count - 1 {
-                        self.nodes[child].subscribers.retain(|s| *s != id);
-                        return;
-                    }
-                    current = child;
-                }
-                literal => {
-                    let Some(&child) = self.nodes[current].children.get(literal) else {
-                        return;
-                    };
-                    if i == count - 1 {
-                        self.nodes[child].subscribers.retain(|s| *s != id);
-                        return;
-                    }
-                    current = child;
-                }
+            if seg == "**" && i == count - 1 {
+                self.nodes[current]
+                    .multi_wildcard_subscribers
+                    .retain(|s| *s != id);
+                return self.prune(current, path);
+            }
+            let Some(child) = self.child(current, Link::of(seg)) else {
+                return;
+            };
+            path.push((current, Link::of(seg)));
+            current = child;
+        }
+        self.nodes[current].subscribers.retain(|s| *s != id);
+        self.prune(current, path);
+    }
+
+    /// Note a subscriber going from `before` to `after` filters.
+    pub fn filters_changed(&mut self, before: usize, after: usize) {
+        match (before > 1, after > 1) {
+            (false, true) => self.multi_filter += 1,
+            (true, false) => self.multi_filter -= 1,
+            _ => {}
+        }
+    }
+
+    fn child(&self, node: NodeId, link: Link<'_>) -> Option<NodeId> {
+        let node = &self.nodes[node];
+        match link {
+            Link::Literal(literal) => node.children.get(literal).copied(),
+            Link::Glob(glob) => node
+                .globs
+                .iter()
+                .find_map(|(pattern, child)| (**pattern == *glob).then_some(*child)),
+            Link::SingleWildcard => node.single_wildcard,
+            Link::MultiWildcard => node.multi_wildcard,
+        }
+    }
+
+    fn link(&mut self, parent: NodeId, link: Link<'_>, child: NodeId) {
+        let parent = &mut self.nodes[parent];
+        match link {
+            Link::Literal(literal) => {
+                parent.children.insert(Arc::from(literal), child);
+            }
+            Link::Glob(glob) => parent.globs.push((Arc::from(glob), child)),
+            Link::SingleWildcard => parent.single_wildcard = Some(child),
+            Link::MultiWildcard => parent.multi_wildcard = Some(child),
+        }
+    }
+
+    fn unlink(&mut self, parent: NodeId, link: Link<'_>) {
+        let parent = &mut self.nodes[parent];
+        match link {
+            Link::Literal(literal) => {
+                parent.children.remove(literal);
+            }
+            Link::Glob(glob) => parent.globs.retain(|(pattern, _)| **pattern != *glob),
+            Link::SingleWildcard => parent.single_wildcard = None,
+            Link::MultiWildcard => parent.multi_wildcard = None,
+        }
+    }
+
+    /// Free `node` and its ancestors along `path` for as long as they are empty.
+    fn prune(&mut self, mut node: NodeId, mut path: Vec<(NodeId, Link<'_>)>) {
+        while let Some((parent, link)) = path.pop() {
+            if !self.nodes[node].is_empty() {
+                return;
             }
+            self.unlink(parent, link);
+            self.nodes[node] = TrieNode::default();
+            self.free.push(node);
+            node = parent;
         }
     }
 
@@ -121,14 +251,29 @@ impl TopicTrie {
         let mut result = Vec::new();
         let seg_count = topic.segment_count();
         let mut stack: Vec<(NodeId, usize)> = vec![(0, 0)];
+        // A filter with a non-trailing `**` can match the same topic in
+        // several ways, so its subscribers may be found more than once.
+        let mut may_repeat = false;
+        // Only nodes below a `**` can be reached twice at the same segment.
+        // Visiting each such state once keeps matching polynomial however
+        // many `**` a filter has.
+        let mut visited: HashSet<(NodeId, usize)> = HashSet::new();
 
         while let Some((node_id, seg_idx)) = stack.pop() {
+            if may_repeat && !visited.insert((node_id, seg_idx)) {
+                continue;
+            }
             let node = &self.nodes[node_id];
 
             node.multi_wildcard_subscribers
                 .iter()
                 .for_each(|id| result.push(*id));
 
+            if let Some(child_id) = node.multi_wildcard {
+                may_repeat = true;
+                stack.extend((seg_idx..=seg_count).map(|i| (child_id, i)));
+            }
+
             if seg_idx == seg_count {
                 node.subscribers.iter().for_each(|id| result.push(*id));
                 continue;
@@ -143,15 +288,115 @@ impl TopicTrie {
             if let Some(&child_id) = node.children.get(seg) {
                 stack.push((child_id, seg_idx + 1));
             }
+
+            node.globs
+                .iter()
+                .filter(|(pattern, _)| glob_matches(pattern, seg))
+                .for_each(|&(_, child_id)| stack.push((child_id, seg_idx + 1)));
+        }
+
+        if may_repeat || self.multi_filter > 0 {
+            result.sort_unstable_by_key(|id| id.0);
+            result.dedup();
+        }
+        result
+    }
+
+    /// List every filter with at least one subscriber, with its subscriber
+    /// count, sorted by filter.
+    pub fn filters(&self) -> Vec<(String, usize)> {
+        let join = |path: &str, seg: &str| {
+            if path.is_empty() {
+                seg.to_string()
+            } else {
+                format!("{path}/{seg}")
+            }
+        };
+
+        let mut result = Vec::new();
+        let mut stack = vec![(0, String::new())];
+
+        while let Some((node_id, path)) = stack.pop() {
+            let node = &self.nodes[node_id];
+
+            if !node.subscribers.is_empty() {
+                result.push((path.clone(), node.subscribers.len()));
+            }
+            if !node.multi_wildcard_subscribers.is_empty() {
+                result.push((join(&path, "**"), node.multi_wildcard_subscribers.len()));
+            }
+            if let Some(child_id) = node.single_wildcard {
+                stack.push((child_id, join(&path, "*")));
+            }
+            if let Some(child_id) = node.multi_wildcard {
+                stack.push((child_id, join(&path, "**")));
+            }
+            node.children
+                .iter()
+                .chain(node.globs.iter().map(|(glob, child_id)| (glob, child_id)))
+                .for_each(|(seg, &child_id)| {
+                    stack.push((child_id, join(&path, seg)));
+                });
         }
 
+        result.sort();
         result
     }
 
     fn alloc_node(&mut self) -> NodeId {
-        let id = self.nodes.len();
-        self.nodes.push(TrieNode::default());
-        id
+        if let Some(id) = self.free.pop() {
+            return id;
+        }
+        self.nodes.push(TrieNode::default())
+    }
+}
+
+/// Copy-on-write routing table.
+///
+/// Publishers read an immutable snapshot without taking any lock, so they
+/// never wait on subscribe or unsubscribe. Writers serialize on the master
+/// trie and publish a fresh snapshot after every change. Snapshots share
+/// nodes with the master, so a change costs one pointer per 64 nodes plus
+/// copies of the nodes it touches, not a copy of the whole trie.
+///
+/// `generation` is bumped after each new snapshot is stored, so a cache
+/// tagged with a generation read *before* loading the snapshot is never
+/// newer than the snapshot it was built from.
+#[derive(Debug)]
+pub(crate) struct Routes {
+    trie: Mutex<TopicTrie>,
+    snapshot: ArcSwap<TopicTrie>,
+    generation: AtomicU64,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self {
+            trie: Mutex::new(TopicTrie::new()),
+            snapshot: ArcSwap::from_pointee(TopicTrie::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Counter that changes whenever the snapshot does.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// The current snapshot.
+    pub fn load(&self) -> Guard<Arc<TopicTrie>> {
+        self.snapshot.load()
+    }
+
+    /// Mutate the master trie and publish the result as the new snapshot.
+    pub fn update<R>(&self, f: impl FnOnce(&mut TopicTrie) -> R) -> R {
+        // A panic mid-update leaves the trie structurally valid, so poisoning
+        // is ignored rather than taking every later unsubscribe down with it.
+        let mut trie = self.trie.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = f(&mut trie);
+        self.snapshot.store(Arc::new(trie.clone()));
+        self.generation.fetch_add(1, Ordering::Release);
+        result
     }
 }
 
@@ -226,6 +471,106 @@ mod tests {
         assert_eq!(trie.matching(&topic("a/b/c")), vec![SubscriberId(1)]);
     }
 
+    #[test]
+    fn double_star_in_the_middle() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game/**/health"), SubscriberId(1));
+        trie.insert(&topic("**/status"), SubscriberId(2));
+
+        assert_eq!(trie.matching(&topic("game/health")), vec![SubscriberId(1)]);
+        assert_eq!(
+            trie.matching(&topic("game/apex/player/health")),
+            vec![SubscriberId(1)]
+        );
+        // `health/health` can be reached two ways but is reported once.
+        assert_eq!(
+            trie.matching(&topic("game/health/health")),
+            vec![SubscriberId(1)]
+        );
+        assert_eq!(trie.matching(&topic("status")), vec![SubscriberId(2)]);
+        assert!(trie.matching(&topic("game/apex/health/max")).is_empty());
+    }
+
+    #[test]
+    fn many_double_stars_match_quickly() {
+        let mut trie = TopicTrie::new();
+        let filter = format!("{}zz", "**/a/".repeat(12));
+        trie.insert(&topic(&filter), SubscriberId(1));
+        trie.insert(&topic("**/**/**/**/**/**/**/**/**/**/zz"), SubscriberId(2));
+
+        let long = topic(&["a"; 40].join("/"));
+        assert!(trie.matching(&long).is_empty());
+        let matched = topic(&format!("{}/zz", ["a"; 40].join("/")));
+        assert_eq!(
+            trie.matching(&matched),
+            vec![SubscriberId(1), SubscriberId(2)]
+        );
+    }
+
+    #[test]
+    fn segment_globs() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game/player-*/health"), SubscriberId(1));
+        trie.insert(&topic("game/*-bot/health"), SubscriberId(2));
+        trie.insert(&topic("game/player-1/health"), SubscriberId(3));
+
+        let sorted = |t: &str| {
+            let mut ids = trie.matching(&topic(t));
+            ids.sort_unstable_by_key(|id| id.0);
+            ids
+        };
+        assert_eq!(
+            sorted("game/player-1/health"),
+            vec![SubscriberId(1), SubscriberId(3)]
+        );
+        assert_eq!(
+            sorted("game/player-bot/health"),
+            vec![SubscriberId(1), SubscriberId(2)]
+        );
+        assert!(sorted("game/enemy/health").is_empty());
+    }
+
+    #[test]
+    fn agrees_with_topic_matches() {
+        let filters = [
+            "**", "a/**", "**/c", "a/**/c", "**/b/**", "a/*/c", "*/*", "a/b*", "*b*/**",
+            "a/**/*-x", "a/b/c",
+        ];
+        let topics = [
+            "a", "a/b", "a/c", "a/b/c", "a/bb/c", "a/b/b/c", "x/b/y", "b", "a/y-x", "a/b/c/d",
+            "ab/c", "c",
+        ];
+
+        let mut trie = TopicTrie::new();
+        filters.iter().enumerate().for_each(|(i, f)| {
+            trie.insert(&topic(f), SubscriberId(i as u64));
+        });
+
+        for t in topics {
+            let matched = trie.matching(&topic(t));
+            for (i, f) in filters.iter().enumerate() {
+                assert_eq!(
+                    matched.contains(&SubscriberId(i as u64)),
+                    crate::topic_matches(&topic(f), &topic(t)),
+                    "filter {f} against {t}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multi_filter_subscribers_match_once() {
+        let mut trie = TopicTrie::new();
+        trie.filters_changed(0, 2);
+        trie.insert(&topic("game/*"), SubscriberId(1));
+        trie.insert(&topic("game/apex"), SubscriberId(1));
+        trie.insert(&topic("game/apex"), SubscriberId(2));
+        assert_eq!(
+            trie.matching(&topic("game/apex")),
+            vec![SubscriberId(1), SubscriberId(2)]
+        );
+    }
+
     #[test]
     fn multiple_subscribers() {
         let mut trie = TopicTrie::new();
@@ -264,6 +609,110 @@ mod tests {
         assert!(trie.matching(&topic("game/valorant")).is_empty());
     }
 
+    #[test]
+    fn list_filters() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("**"), SubscriberId(1));
+        trie.insert(&topic("game/*/status"), SubscriberId(2));
+        trie.insert(&topic("game/*/status"), SubscriberId(3));
+        trie.insert(&topic("game/valorant"), SubscriberId(4));
+        trie.insert(&topic("game/valorant/**"), SubscriberId(5));
+
+        assert_eq!(
+            trie.filters(),
+            vec![
+                (String::from("**"), 1),
+                (String::from("game/*/status"), 2),
+                (String::from("game/valorant"), 1),
+                (String::from("game/valorant/**"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_prunes_empty_nodes() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game/*/status"), SubscriberId(1));
+        trie.insert(&topic("game/valorant/**"), SubscriberId(2));
+        assert_eq!(trie.node_count(), 5);
+
+        trie.remove(&topic("game/*/status"), SubscriberId(1));
+        assert_eq!(trie.node_count(), 3);
+        assert_eq!(
+            trie.matching(&topic("game/valorant/status")),
+            vec![SubscriberId(2)]
+        );
+
+        trie.remove(&topic("game/valorant/**"), SubscriberId(2));
+        assert_eq!(trie.node_count(), 1);
+        assert!(trie.filters().is_empty());
+    }
+
+    #[test]
+    fn remove_prunes_glob_and_inner_double_star() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game/**/health"), SubscriberId(1));
+        trie.insert(&topic("game/player-*"), SubscriberId(2));
+        assert_eq!(
+            trie.filters(),
+            vec![
+                (String::from("game/**/health"), 1),
+                (String::from("game/player-*"), 1),
+            ]
+        );
+
+        trie.remove(&topic("game/**/health"), SubscriberId(1));
+        assert_eq!(trie.node_count(), 3);
+        trie.remove(&topic("game/player-*"), SubscriberId(2));
+        assert_eq!(trie.node_count(), 1);
+    }
+
+    #[test]
+    fn remove_keeps_shared_prefix() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game"), SubscriberId(1));
+        trie.insert(&topic("game/valorant"), SubscriberId(2));
+
+        trie.remove(&topic("game/valorant"), SubscriberId(2));
+        assert_eq!(trie.node_count(), 2);
+        assert_eq!(trie.matching(&topic("game")), vec![SubscriberId(1)]);
+    }
+
+    #[test]
+    fn arena_stays_flat_under_churn() {
+        let mut trie = TopicTrie::new();
+        trie.insert(&topic("game/**"), SubscriberId(0));
+
+        (1..10_000).for_each(|i| {
+            let filter = topic(&format!("match/{i}/**"));
+            trie.insert(&filter, SubscriberId(i));
+            trie.remove(&filter, SubscriberId(i));
+        });
+
+        assert_eq!(trie.node_count(), 2);
+        assert!(trie.nodes.len() <= 4);
+    }
+
+    #[test]
+    fn snapshots_share_untouched_nodes() {
+        let routes = Routes::new();
+        routes.update(|trie| {
+            (0..200).for_each(|i| trie.insert(&topic(&format!("a/{i}")), SubscriberId(i)));
+        });
+        let before = routes.snapshot.load_full();
+
+        routes.update(|trie| trie.insert(&topic("a/new"), SubscriberId(200)));
+        let after = routes.load();
+        assert!(Arc::ptr_eq(&before.nodes.chunks[1], &after.nodes.chunks[1]));
+        assert!(!Arc::ptr_eq(
+            &before.nodes.chunks[0],
+            &after.nodes.chunks[0]
+        ));
+        assert!(before.matching(&topic("a/new")).is_empty());
+        assert_eq!(after.matching(&topic("a/new")), vec![SubscriberId(200)]);
+        assert_eq!(after.matching(&topic("a/150")), vec![SubscriberId(150)]);
+    }
+
     #[test]
     fn remove_nonexistent_is_noop() {
         let mut trie = TopicTrie::new();
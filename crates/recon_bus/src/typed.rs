@@ -0,0 +1,204 @@
+//! Topics with a compile-time payload type.
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{Bus, BusError, DeliveryMode, Subscription, Topic, TopicError, topic_matches};
+
+/// A topic pattern bound to the JSON payload type `T`.
+///
+/// Declare one per contract and share it between publishers and
+/// subscribers:
+///
+/// ```ignore
+/// const HEALTH: TypedTopic<Health> = TypedTopic::new("game/*/health");
+///
+/// HEALTH.publish_to(&bus, "game/valorant/health", &Health { hp: 100 })?;
+/// let mut sub = HEALTH.subscribe(&bus)?;
+/// ```
+///
+/// The pattern is validated when the topic is first used, so an invalid
+/// pattern surfaces as [`BusError::Topic`] from every method.
+pub struct TypedTopic<T> {
+    pattern: &'static str,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedTopic<T> {
+    pub const fn new(pattern: &'static str) -> Self {
+        Self {
+            pattern,
+            _payload: PhantomData,
+        }
+    }
+
+    pub const fn pattern(&self) -> &'static str {
+        self.pattern
+    }
+}
+
+impl<T: Serialize> TypedTopic<T> {
+    /// Publish `value` to the pattern itself, which must not contain
+    /// wildcards.
+    pub fn publish(&self, bus: &Bus, value: &T) -> Result<usize, BusError> {
+        self.publish_to(bus, self.pattern, value)
+    }
+
+    /// Publish `value` to a concrete `topic` matching the pattern.
+    pub fn publish_to(
+        &self,
+        bus: &Bus,
+        topic: impl TryInto<Topic, Error = TopicError>,
+        value: &T,
+    ) -> Result<usize, BusError> {
+        let pattern = Topic::try_from(self.pattern)?;
+        let topic = topic.try_into()?;
+        if topic.has_wildcards() || !topic_matches(&pattern, &topic) {
+            return Err(BusError::PatternMismatch(topic.into_arc()));
+        }
+        bus.publish_serde(&topic, value)
+    }
+}
+
+impl<T: DeserializeOwned> TypedTopic<T> {
+    /// Subscribe to the pattern. Uses [`DeliveryMode::Latest`].
+    pub fn subscribe(&self, bus: &Bus) -> Result<TypedSubscription<T>, BusError> {
+        self.subscribe_with(bus, DeliveryMode::Latest)
+    }
+
+    /// Subscribe to the pattern with an explicit delivery mode.
+    pub fn subscribe_with(
+        &self,
+        bus: &Bus,
+        mode: DeliveryMode,
+    ) -> Result<TypedSubscription<T>, BusError> {
+        Ok(TypedSubscription {
+            sub: bus.subscribe_with(self.pattern, mode)?,
+            _payload: PhantomData,
+        })
+    }
+}
+
+impl<T> Clone for TypedTopic<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedTopic<T> {}
+
+impl<T> fmt::Debug for TypedTopic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedTopic").field(&self.pattern).finish()
+    }
+}
+
+/// A [`Subscription`] that decodes each payload as `T`.
+///
+/// A payload that fails to decode, for example one published as a raw
+/// string to the same topic, is yielded as an error rather than skipped.
+pub struct TypedSubscription<T> {
+    sub: Subscription,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> TypedSubscription<T> {
+    /// Wait for the next message and decode it.
+    pub async fn recv(&mut self) -> Option<Result<T, serde_json::Error>> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Poll for the next message. See [`TypedSubscription::recv`].
+    pub fn poll_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<T, serde_json::Error>>> {
+        self.sub
+            .poll_recv(cx)
+            .map(|envelope| envelope.map(|e| e.deserialize()))
+    }
+
+    /// Decode the latest value delivered to this subscription.
+    pub fn get(&self) -> Option<Result<T, serde_json::Error>> {
+        self.sub.get().map(|e| e.deserialize())
+    }
+
+    /// The underlying subscription, for envelope metadata such as the
+    /// concrete topic.
+    pub fn into_inner(self) -> Subscription {
+        self.sub
+    }
+}
+
+impl<T: DeserializeOwned> Stream for TypedSubscription<T> {
+    type Item = Result<T, serde_json::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        hp: u32,
+    }
+
+    const HEALTH: TypedTopic<Health> = TypedTopic::new("game/*/health");
+    const STATUS: TypedTopic<String> = TypedTopic::new("status");
+
+    #[tokio::test]
+    async fn typed_roundtrip() {
+        let bus = Bus::new();
+        let mut sub = HEALTH.subscribe(&bus).unwrap();
+
+        HEALTH
+            .publish_to(&bus, "game/valorant/health", &Health { hp: 80 })
+            .unwrap();
+        assert_eq!(sub.recv().await.unwrap().unwrap(), Health { hp: 80 });
+        assert_eq!(sub.get().unwrap().unwrap(), Health { hp: 80 });
+
+        bus.publish("game/valorant/health", r#"{"health":1}"#)
+            .unwrap();
+        assert!(sub.recv().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn concrete_topic_publish() {
+        let bus = Bus::new();
+        let mut sub = STATUS.subscribe(&bus).unwrap();
+
+        STATUS.publish(&bus, &"online".to_string()).unwrap();
+        assert_eq!(sub.recv().await.unwrap().unwrap(), "online");
+    }
+
+    #[test]
+    fn rejects_topics_outside_pattern() {
+        let bus = Bus::new();
+        let hp = Health { hp: 1 };
+
+        assert!(matches!(
+            HEALTH.publish_to(&bus, "chat/health", &hp),
+            Err(BusError::PatternMismatch(_))
+        ));
+        assert!(matches!(
+            HEALTH.publish(&bus, &hp),
+            Err(BusError::PatternMismatch(_))
+        ));
+
+        const BROKEN: TypedTopic<Health> = TypedTopic::new("game//health");
+        assert!(matches!(BROKEN.subscribe(&bus), Err(BusError::Topic(_))));
+    }
+}
@@ -0,0 +1,311 @@
+//! WebSocket gateway for browser clients such as OBS overlays.
+//!
+//! Speaks the same JSON [`Frame`](crate::ipc::Frame)s as the [IPC bridge](crate::ipc), one per
+//! text message. Clients authenticate with a shared token, either as a
+//! `token` query parameter (`ws://127.0.0.1:7423/?token=...`, which is all a
+//! browser `WebSocket` can send) or as an `Authorization: Bearer` header.
+
+use std::{io, sync::Arc};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, ToSocketAddrs},
+};
+use tokio_tungstenite::tungstenite::{
+    self, Message,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+};
+
+use crate::{AccessPolicy, Bus, FilterList, Principal, Topic, TopicError, ipc::Session};
+
+/// Address the gateway listens on unless told otherwise. Loopback only.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7423";
+
+/// Serves the bus to WebSocket clients holding a token.
+///
+/// Clients may subscribe to any filter but publish nothing until topics are
+/// allow-listed:
+///
+/// ```ignore
+/// WsGateway::new(&bus, token)
+///     .allow_publish("overlay/**")?
+///     .serve(ws::DEFAULT_ADDR)
+///     .await?;
+/// ```
+pub struct WsGateway {
+    bus: Bus,
+    token: Arc<str>,
+    policy: AccessPolicy,
+}
+
+impl WsGateway {
+    pub fn new(bus: &Bus, token: impl Into<Arc<str>>) -> Self {
+        Self {
+            bus: bus.clone(),
+            token: token.into(),
+            policy: AccessPolicy {
+                publish: FilterList::deny_all(),
+                subscribe: FilterList::allow_all(),
+            },
+        }
+    }
+
+    /// Let clients publish to topics matching `filter`.
+    pub fn allow_publish(
+        mut self,
+        filter: impl TryInto<Topic, Error = TopicError>,
+    ) -> Result<Self, TopicError> {
+        self.policy.publish = self.policy.publish.allow(filter)?;
+        Ok(self)
+    }
+
+    /// Replace the filters clients may subscribe with. Defaults to every
+    /// topic.
+    pub fn subscribe_filters(mut self, filters: FilterList) -> Self {
+        self.policy.subscribe = filters;
+        self
+    }
+
+    /// Accept TCP connections on `addr` until an accept fails.
+    pub async fn serve(self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let gateway = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let gateway = Arc::clone(&gateway);
+            tokio::spawn(async move { gateway.serve_connection(stream).await });
+        }
+    }
+
+    /// Run the WebSocket handshake on an accepted stream, then speak the
+    /// protocol until the client disconnects. Connections without the
+    /// token are refused with `401 Unauthorized`.
+    pub async fn serve_connection<S>(&self, stream: S) -> Result<(), tungstenite::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        // The refusal type is fixed by tungstenite.
+        #[allow(clippy::result_large_err)]
+        let authorize = |request: &Request, response: Response| {
+            if self.authorized(request) {
+                Ok(response)
+            } else {
+                let mut refusal = ErrorResponse::new(Some("invalid or missing token".into()));
+                *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(refusal)
+            }
+        };
+        let socket = tokio_tungstenite::accept_hdr_async(stream, authorize).await?;
+        let (mut sink, mut incoming) = socket.split();
+
+        let principal = Principal::new("websocket", self.policy.clone());
+        let (mut session, mut outgoing) = Session::new(&self.bus, principal);
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                let json = serde_json::to_string(&frame).expect("frames always serialize");
+                sink.send(Message::text(json)).await?;
+            }
+            sink.close().await
+        });
+
+        let result = loop {
+            match incoming.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    if !session.receive(&text).await {
+                        break Ok(());
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => break Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break Err(e),
+            }
+        };
+
+        drop(session);
+        let closed = writer.await.unwrap_or(Ok(()));
+        result.and(closed.or_else(ignore_closed))
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        let from_query = request.uri().query().into_iter().flat_map(|query| {
+            query
+                .split('&')
+                .filter_map(|pair| percent_decode(pair.strip_prefix("token=")?))
+        });
+        let from_header = request
+            .headers()
+            .get_all("authorization")
+            .into_iter()
+            .filter_map(|value| value.to_str().ok()?.strip_prefix("Bearer "))
+            .map(|token| token.as_bytes().to_vec());
+        from_query
+            .chain(from_header)
+            .any(|token| constant_time_eq(&token, self.token.as_bytes()))
+    }
+}
+
+/// A client hanging up first is not an error.
+fn ignore_closed(e: tungstenite::Error) -> Result<(), tungstenite::Error> {
+    match e {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Ok(()),
+        e => Err(e),
+    }
+}
+
+/// Decode a URL query value, where `+` stands for a space. `None` if a `%`
+/// is not followed by two hex digits.
+fn percent_decode(value: &str) -> Option<Vec<u8>> {
+    let mut bytes = value.bytes();
+    let mut decoded = Vec::with_capacity(value.len());
+    while let Some(byte) = bytes.next() {
+        decoded.push(match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            b'+' => b' ',
+            byte => byte,
+        });
+    }
+    Some(decoded)
+}
+
+/// Compare tokens without leaking how long the matching prefix is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::DuplexStream;
+    use tokio_tungstenite::{
+        WebSocketStream, client_async, tungstenite::client::IntoClientRequest,
+    };
+
+    use super::*;
+    use crate::{JournalPayload, ipc::Frame};
+
+    async fn connect(
+        gateway: WsGateway,
+        url: &str,
+    ) -> Result<WebSocketStream<DuplexStream>, tungstenite::Error> {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move { gateway.serve_connection(server).await });
+        let request = url.into_client_request()?;
+        Ok(client_async(request, client).await?.0)
+    }
+
+    async fn send(socket: &mut WebSocketStream<DuplexStream>, frame: Frame) {
+        let json = serde_json::to_string(&frame).unwrap();
+        socket.send(Message::text(json)).await.unwrap();
+    }
+
+    async fn next(socket: &mut WebSocketStream<DuplexStream>) -> Frame {
+        let message = socket.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requires_token() {
+        let bus = Bus::new();
+        for url in ["ws://localhost/", "ws://localhost/?token=wrong"] {
+            let err = connect(WsGateway::new(&bus, "secret"), url)
+                .await
+                .unwrap_err();
+            let tungstenite::Error::Http(response) = err else {
+                panic!("expected an HTTP refusal, got {err:?}");
+            };
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let mut request = "ws://localhost/".into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let (client, server) = tokio::io::duplex(4096);
+        let gateway = WsGateway::new(&bus, "secret");
+        tokio::spawn(async move { gateway.serve_connection(server).await });
+        assert!(client_async(request, client).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn subscribe_and_publish_allow_listed_topics() {
+        let bus = Bus::new();
+        let gateway = WsGateway::new(&bus, "secret")
+            .allow_publish("overlay/**")
+            .unwrap();
+        let mut socket = connect(gateway, "ws://localhost/?v=1&token=secret")
+            .await
+            .unwrap();
+
+        for filter in ["overlay/**", "game/**"] {
+            send(
+                &mut socket,
+                Frame::Sub {
+                    filter: filter.into(),
+                },
+            )
+            .await;
+        }
+        send(
+            &mut socket,
+            Frame::Pub {
+                topic: "overlay/scene".into(),
+                payload: JournalPayload::Text("intro".into()),
+                retain: false,
+            },
+        )
+        .await;
+        let Frame::Msg {
+            topic, publisher, ..
+        } = next(&mut socket).await
+        else {
+            panic!("expected the overlay publish to come back");
+        };
+        assert_eq!(topic, "overlay/scene");
+        assert_eq!(publisher.as_deref(), Some("websocket"));
+
+        bus.publish("game/apex/status", "online").unwrap();
+        let Frame::Msg { payload, .. } = next(&mut socket).await else {
+            panic!("expected a bus message");
+        };
+        assert_eq!(payload, JournalPayload::Text("online".into()));
+
+        send(
+            &mut socket,
+            Frame::Pub {
+                topic: "settings/theme".into(),
+                payload: JournalPayload::Text("dark".into()),
+                retain: true,
+            },
+        )
+        .await;
+        assert!(matches!(next(&mut socket).await, Frame::Error { .. }));
+        assert!(bus.retained("settings/theme").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn accepts_percent_encoded_token() {
+        let bus = Bus::new();
+        let gateway = WsGateway::new(&bus, "s3cr&t=+/ %");
+        let url = "ws://localhost/?token=s3cr%26t%3D%2B%2F+%25";
+        assert!(connect(gateway, url).await.is_ok());
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%2Fb+c").as_deref(), Some(&b"a/b c"[..]));
+        assert_eq!(percent_decode("plain").as_deref(), Some(&b"plain"[..]));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
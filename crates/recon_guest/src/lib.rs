@@ -51,4 +51,24 @@ wit_bindgen::generate!({
 
 pub mod bus {
     pub use super::recon::event_bus::bus::*;
+
+    // The same helpers as `recon_bus::guest`, kept here so plugins do not
+    // depend on the host-side bus crate.
+    impl Payload {
+        /// The payload as text, or `None` for binary payloads.
+        pub fn as_str(&self) -> Option<&str> {
+            match self {
+                Self::Text(text) => Some(text),
+                Self::Binary(_) => None,
+            }
+        }
+
+        /// The raw bytes of the payload. Text payloads yield their UTF-8 bytes.
+        pub fn as_bytes(&self) -> &[u8] {
+            match self {
+                Self::Text(text) => text.as_bytes(),
+                Self::Binary(bytes) => bytes,
+            }
+        }
+    }
 }